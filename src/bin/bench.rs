@@ -0,0 +1,39 @@
+use aoc2019::days::*;
+use aoc2019::intcode::compiled::Engine;
+use aoc2019::solver::Solver;
+use std::time::{Duration, Instant};
+
+const RUNS: usize = 25;
+
+macro_rules! bench {
+    ( $d:expr ) => {
+        paste::expr! {
+            bench::<[<day $d>]::[<Day $d>]>($d, include_str!(concat!("../../input/2019/day", $d, ".txt")));
+        }
+    };
+}
+
+fn main() {
+    for &engine in &[Engine::Interpreter, Engine::Compiled] {
+        Engine::set_default(engine);
+        println!("{:?}", engine);
+        bench!(9);
+        bench!(13);
+        bench!(15);
+    }
+}
+
+fn bench<'a, S: Solver<'a>>(day_number: u8, input: &'a str) {
+    let generated = S::generator(input.trim());
+    let mut times = (0..RUNS)
+        .map(|_| {
+            let start_time = Instant::now();
+            S::part1(generated.clone());
+            S::part2(generated.clone());
+            start_time.elapsed()
+        })
+        .collect::<Vec<Duration>>();
+    times.sort();
+
+    println!("\tday {:>2}: median {:?}", day_number, times[RUNS / 2]);
+}
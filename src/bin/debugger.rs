@@ -0,0 +1,41 @@
+use aoc2019::intcode::debugger::Debugger;
+use aoc2019::intcode::IntCode;
+use std::io::{self, BufRead, Write};
+
+fn main() {
+    let path = match std::env::args().nth(1) {
+        Some(path) => path,
+        None => {
+            eprintln!("usage: debugger <program file>");
+            std::process::exit(1);
+        }
+    };
+
+    let intcode = std::fs::read_to_string(&path)
+        .unwrap()
+        .trim()
+        .parse::<IntCode>()
+        .unwrap();
+    let mut debugger = Debugger::new(intcode);
+
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+
+    loop {
+        print!("(icdb) ");
+        io::stdout().flush().unwrap();
+
+        let line = match lines.next() {
+            Some(line) => line.unwrap(),
+            None => break,
+        };
+
+        match line.trim() {
+            "q" | "quit" => break,
+            command => match debugger.execute(command) {
+                Ok(output) => print!("{}", output),
+                Err(error) => println!("error: {}", error),
+            },
+        }
+    }
+}
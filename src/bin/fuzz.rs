@@ -0,0 +1,46 @@
+use aoc2019::intcode::fuzz::{Case, Xorshift};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const DEFAULT_CASES: u64 = 100_000;
+
+fn main() {
+    let mut args = std::env::args().skip(1).map(|arg| arg.parse::<u64>());
+    let seed = match args.next() {
+        Some(Ok(seed)) => seed,
+        None => SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_nanos() as u64,
+        Some(Err(_)) => usage(),
+    };
+    let cases = match args.next() {
+        Some(Ok(cases)) => cases,
+        None => DEFAULT_CASES,
+        Some(Err(_)) => usage(),
+    };
+
+    // Panics are caught and reported with their case instead.
+    std::panic::set_hook(Box::new(|_| ()));
+
+    println!("seed {}", seed);
+    let mut rng = Xorshift::new(seed);
+    let mut failures = 0;
+
+    for _ in 0..cases {
+        let case = Case::generate(&mut rng);
+        if let Err(failure) = case.check() {
+            println!("{}\n{}\n", failure, case);
+            failures += 1;
+        }
+    }
+
+    println!("{} of {} cases failed", failures, cases);
+    if failures > 0 {
+        std::process::exit(1);
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("usage: fuzz [seed] [cases]");
+    std::process::exit(1);
+}
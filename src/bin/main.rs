@@ -1,4 +1,6 @@
 use aoc2019::days::*;
+use aoc2019::intcode::compiled::Engine;
+use aoc2019::intcode::memo::Memo;
 use aoc2019::solver::Solver;
 use std::cmp::PartialEq;
 use std::fmt::Debug;
@@ -25,6 +27,28 @@ macro_rules! day {
 }
 
 fn main() {
+    let mut args = std::env::args().skip(1);
+    while let Some(flag) = args.next() {
+        match (flag.as_str(), args.next()) {
+            ("--engine", Some(engine)) => match engine.parse() {
+                Ok(engine) => Engine::set_default(engine),
+                Err(error) => {
+                    eprintln!("{}", error);
+                    usage();
+                }
+            },
+            // Without it, nothing is memoized: the puzzles rarely repeat a run.
+            ("--cache", Some(dir)) => match Memo::with_dir(&dir) {
+                Ok(memo) => Memo::set_shared(memo),
+                Err(error) => {
+                    eprintln!("can't use {} as a cache: {}", dir, error);
+                    std::process::exit(1);
+                }
+            },
+            _ => usage(),
+        }
+    }
+
     println!("AOC 2019");
     day!(1, 3336439, 5001791);
     day!(2, 4090701, 6421);
@@ -45,6 +69,11 @@ fn main() {
     day!(17, 5740, 1022165);
 }
 
+fn usage() -> ! {
+    eprintln!("usage: main [--engine interpreter|compiled] [--cache <dir>]");
+    std::process::exit(1);
+}
+
 fn run<'a, S: Solver<'a>>(
     day_number: u8,
     input: &'a str,
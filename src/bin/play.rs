@@ -0,0 +1,54 @@
+use aoc2019::intcode::play::*;
+use aoc2019::intcode::IntCode;
+use std::io;
+
+fn main() {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let (breakout, path) = match &args[..] {
+        [flag, path] if flag == "--breakout" => (true, path),
+        [path] => (false, path),
+        _ => {
+            eprintln!("usage: play [--breakout] <program file>");
+            std::process::exit(1);
+        }
+    };
+
+    let mut intcode = std::fs::read_to_string(path)
+        .unwrap()
+        .trim()
+        .parse::<IntCode>()
+        .unwrap();
+
+    let mut renderer: Box<dyn Renderer> = if breakout {
+        // Insert quarters so the game doesn't stop after the first screen.
+        intcode.replace_cell(0, 2);
+        Box::new(BreakoutRenderer::new())
+    } else {
+        Box::new(AsciiRenderer::new())
+    };
+
+    // Restores the terminal when dropped, before any exit.
+    let terminal = if renderer.keypresses() {
+        match RawTerminal::new() {
+            Ok(terminal) => Some(terminal),
+            Err(error) => {
+                eprintln!("can't read single keys: {}", error);
+                std::process::exit(1);
+            }
+        }
+    } else {
+        None
+    };
+
+    let stdin = io::stdin();
+    let result = play(intcode, renderer.as_mut(), stdin.lock(), io::stdout());
+    drop(terminal);
+    match result {
+        Ok(true) => (),
+        Ok(false) => println!(),
+        Err(error) => {
+            eprintln!("error: {}", error);
+            std::process::exit(1);
+        }
+    }
+}
@@ -3,7 +3,6 @@ use crate::solver::Solver;
 use noisy_float::prelude::*;
 use std::collections::HashSet;
 use std::f32::consts::*;
-use std::f32::EPSILON;
 
 type F = f32;
 type R = R32;
@@ -58,7 +57,7 @@ fn find_best_coord(asteroid_coords: &<Day10 as Solver>::Generated) -> (Point, us
     asteroid_coords
         .iter()
         .copied()
-        .zip(visible_count.into_iter())
+        .zip(visible_count)
         .max_by_key(|&(_, c)| c)
         .unwrap()
 }
@@ -125,7 +124,7 @@ fn calc_angle_and_distance(p1: Point, p2: Point) -> (R, R) {
 }
 
 fn float_equals(x1: R, x2: R) -> bool {
-    (x1 - x2).abs() < EPSILON
+    (x1 - x2).abs() < F::EPSILON
 }
 #[cfg(test)]
 mod tests {
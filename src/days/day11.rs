@@ -2,6 +2,7 @@ use crate::coord_system::direction::*;
 use crate::coord_system::signed::*;
 use crate::intcode::*;
 use crate::solver::Solver;
+use std::cell::Cell;
 use std::collections::HashMap;
 
 pub struct Day11 {}
@@ -64,33 +65,28 @@ impl Solver<'_> for Day11 {
 }
 
 fn run_bot(intcode: IntCode, start_value: IntCodeCell) -> HashMap<Point, IntCodeCell> {
-    let position = Point { x: 0, y: 0 };
+    let mut position = Point { x: 0, y: 0 };
     let mut direction = Direction::Up;
     let mut grid = HashMap::new();
-    let mut previous_output = None;
+    // The colour under the robot, read by the program whenever it looks down.
+    let current = Cell::new(start_value);
 
     grid.insert(position, start_value);
 
-    intcode.run_with_fns(
-        (&mut grid, position),
-        |(grid, position), o| {
-            if let Some(pos) = previous_output {
-                grid.insert(*position, pos);
-
-                if o == 0 {
-                    direction = direction.turn_left();
-                } else {
-                    direction = direction.turn_right();
-                }
-
-                *position = position.add_dir(direction);
-                previous_output = None;
-            } else {
-                previous_output = Some(o);
-            }
-        },
-        |(grid, position)| *grid.get(position).unwrap_or(&0),
-    );
+    let mut moves = intcode.outputs(|| Some(current.get())).tuples::<2>();
+    for [colour, turn] in moves.by_ref() {
+        grid.insert(position, colour);
+
+        if turn == 0 {
+            direction = direction.turn_left();
+        } else {
+            direction = direction.turn_right();
+        }
+
+        position = position.add_dir(direction);
+        current.set(*grid.get(&position).unwrap_or(&0));
+    }
+    moves.inner().end().unwrap().unwrap();
 
     grid
 }
@@ -1,6 +1,6 @@
 use crate::intcode::*;
 use crate::solver::Solver;
-use std::cmp::Ordering;
+use std::cell::Cell;
 
 pub struct Day13 {}
 
@@ -13,43 +13,30 @@ impl Solver<'_> for Day13 {
     }
 
     fn part1(intcode: Self::Generated) -> Self::Output {
-        let outputs = intcode.run_with_input(&[]);
-        outputs.chunks(3).filter(|&x| x[2] == 2).count() as IntCodeCell
+        let mut tiles = intcode.outputs(|| None).tuples::<3>();
+        let blocks = tiles.by_ref().filter(|[_, _, tile]| *tile == 2).count();
+        tiles.inner().end().unwrap().unwrap();
+        blocks as IntCodeCell
     }
 
     fn part2(mut intcode: Self::Generated) -> Self::Output {
         intcode.replace_cell(0, 2);
         let mut score = 0;
-        let mut previous_outputs = [None, None];
-
-        intcode.run_with_fns(
-            (0, 0),
-            |(paddle_x, ball_x), o| {
-                if let Some(x) = previous_outputs[0] {
-                    if let Some(y) = previous_outputs[1] {
-                        let tile = o;
-
-                        if x == -1 && y == 0 {
-                            score = tile;
-                        } else if tile == 3 {
-                            *paddle_x = x;
-                        } else if tile == 4 {
-                            *ball_x = x;
-                        }
-                        previous_outputs = [None, None];
-                    } else {
-                        previous_outputs[1] = Some(o);
-                    }
-                } else {
-                    previous_outputs[0] = Some(o);
-                }
-            },
-            |(paddle_x, ball_x)| match paddle_x.cmp(&ball_x) {
-                Ordering::Less => 1,
-                Ordering::Equal => 0,
-                Ordering::Greater => -1,
-            },
-        );
+        let (paddle_x, ball_x) = (Cell::new(0 as IntCodeCell), Cell::new(0));
+
+        // Keep the paddle under the ball.
+        let mut tiles = intcode
+            .outputs(|| Some((ball_x.get() - paddle_x.get()).signum()))
+            .tuples::<3>();
+        for [x, y, tile] in tiles.by_ref() {
+            match (x, y, tile) {
+                (-1, 0, value) => score = value,
+                (x, _, 3) => paddle_x.set(x),
+                (x, _, 4) => ball_x.set(x),
+                _ => (),
+            }
+        }
+        tiles.inner().end().unwrap().unwrap();
 
         score
     }
@@ -36,11 +36,7 @@ impl<'a> Solver<'a> for Day14 {
             .lines()
             .map(|l| {
                 let arrow = l.find("=>").unwrap();
-                let inputs = l[..arrow]
-                    .trim()
-                    .split(", ")
-                    .map(|x| RecipePart::new(x))
-                    .collect();
+                let inputs = l[..arrow].trim().split(", ").map(RecipePart::new).collect();
                 let output = RecipePart::new(l[arrow + 2..].trim());
                 (output.chemical, Recipe { inputs, output })
             })
@@ -25,64 +25,62 @@ impl Solver<'_> for Day15 {
     }
 }
 
-fn run_bot(intcode: IntCode) -> (N, N) {
+fn run_bot(mut intcode: IntCode) -> (N, N) {
     use Direction::*;
     let all_directions: Vec<_> = ALL_DIRECTIONS[..].into();
 
-    let position = Point { x: 0, y: 0 };
+    let mut position = Point { x: 0, y: 0 };
     let mut objective_distance = 0;
     let mut max_distance = 0;
 
     let mut reset = false;
-    let origin = Point { x: 0, y: 0 };
-    let distance = 0;
-    let last_direction = Up;
+    let mut origin = Point { x: 0, y: 0 };
+    let mut distance = 0;
+    let mut last_direction = Up;
     let mut data = HashMap::new();
     data.insert(position, (all_directions.clone(), Up));
 
-    intcode.run_with_fns(
-        (data, position, origin, distance, last_direction),
-        |(data, position, origin, distance, last_direction), o| match o {
-            0 => (),
-            1 | 2 => {
-                *position = position.add_dir(*last_direction);
-                *distance += 1;
+    loop {
+        match intcode.resume().unwrap() {
+            StepResult::NeedsInput => {
+                let coord_data = data.get_mut(&position).unwrap();
+                if coord_data.0.is_empty() {
+                    if position == origin {
+                        break;
+                    }
+
+                    distance -= 2;
+                    last_direction = coord_data.1;
+                } else {
+                    last_direction = coord_data.0.pop().unwrap();
+                }
+                intcode.provide_input(to_value(last_direction));
+            }
+            StepResult::Output(0) => (),
+            StepResult::Output(o @ 1) | StepResult::Output(o @ 2) => {
+                position = position.add_dir(last_direction);
+                distance += 1;
 
                 if o == 2 && !reset {
-                    objective_distance = *distance;
+                    objective_distance = distance;
 
-                    *origin = *position;
-                    *distance = 0;
+                    origin = position;
+                    distance = 0;
                     data.clear();
-                    data.insert(*position, (all_directions.clone(), Up));
+                    data.insert(position, (all_directions.clone(), Up));
                     max_distance = 0;
                     reset = true;
                 } else {
-                    max_distance = std::cmp::max(max_distance, *distance);
+                    max_distance = std::cmp::max(max_distance, distance);
                     let go_back = last_direction.opposite();
                     let mut next_steps = all_directions.clone();
                     next_steps.remove((to_value(go_back) - 1) as usize);
-                    data.entry(*position).or_insert((next_steps, go_back));
+                    data.entry(position).or_insert((next_steps, go_back));
                 }
             }
             _ => unreachable!(),
-        },
-        |(data, position, origin, distance, last_direction)| {
-            let coord_data = data.get_mut(position).unwrap();
-            if coord_data.0.is_empty() {
-                if position == origin {
-                    99
-                } else {
-                    *distance -= 2;
-                    *last_direction = coord_data.1;
-                    to_value(coord_data.1)
-                }
-            } else {
-                *last_direction = coord_data.0.pop().unwrap();
-                to_value(*last_direction)
-            }
-        },
-    );
+        }
+    }
 
     (objective_distance, max_distance)
 }
@@ -1,6 +1,7 @@
 use crate::coord_system::direction::*;
 use crate::coord_system::grid::*;
 use crate::coord_system::unsigned::*;
+use crate::intcode::ascii::*;
 use crate::intcode::*;
 use crate::solver::Solver;
 
@@ -29,9 +30,9 @@ impl Solver<'_> for Day17 {
     fn generator(input: &str) -> Self::Generated {
         let intcode = input.parse::<IntCode>().unwrap();
 
-        let mut outputs = intcode.clone().run_with_input(&[]);
-        outputs.truncate(outputs.len() - 1);
-        let (grid, robot_pos) = parse_grid(outputs.iter().map(|&x| x as u8 as char));
+        let transcript = Ascii::new(intcode.clone()).transcript().unwrap();
+        let text = transcript.text;
+        let (grid, robot_pos) = parse_grid(text[..text.len() - 1].chars());
 
         (intcode, grid, robot_pos)
     }
@@ -44,15 +45,12 @@ impl Solver<'_> for Day17 {
         let (mut intcode, grid, robot_pos) = stuff;
 
         let path = compute_path(grid, robot_pos);
-        let format_path = format_path(path);
-        let input = format_path
-            .chars()
-            .map(|c| c as IntCodeCell)
-            .collect::<Vec<_>>();
 
         intcode.replace_cell(0, 2);
-        let outputs = intcode.run_with_input(&input);
-        *outputs.last().unwrap() as usize
+        let mut ascii = Ascii::new(intcode);
+        ascii.send_str(&format_path(path));
+        let transcript = ascii.transcript().unwrap();
+        *transcript.values.last().unwrap() as usize
     }
 }
 
@@ -205,11 +203,7 @@ fn longest(path: &[PathSegment]) -> (Vec<usize>, usize) {
     for i in 0..path.len() {
         for j in i + 2..std::cmp::min(path.len(), i + 2 + 4) {
             let pattern = &path[i..j];
-            if pattern.len() > length
-                && pattern
-                    .iter()
-                    .all(|x| if let Movement(_, _) = x { true } else { false })
-            {
+            if pattern.len() > length && pattern.iter().all(|x| matches!(x, Movement(_, _))) {
                 let mut instances = vec![i];
                 let mut range_check = j..=path.len() - pattern.len();
                 while let Some(k) = range_check.next() {
@@ -1,31 +1,51 @@
+use crate::intcode::search::Candidate;
 use crate::intcode::*;
 use crate::solver::Solver;
+use rayon::prelude::*;
+
+const TARGET: IntCodeCell = 19_690_720;
 
 pub struct Day2 {}
 
 impl Solver<'_> for Day2 {
-    type Generated = IntCode;
+    type Generated = IntCodeBuilder;
     type Output = IntCodeCell;
 
     fn generator(input: &str) -> Self::Generated {
         input.parse().unwrap()
     }
 
-    fn part1(intcode: Self::Generated) -> Self::Output {
-        intcode.run_no_io(&[(1, 12), (2, 2)])[0]
+    fn part1(program: Self::Generated) -> Self::Output {
+        program
+            .patch(1, 12)
+            .patch(2, 2)
+            .run_memoized()
+            .unwrap()
+            .memory[0]
     }
 
-    fn part2(start_intcode: Self::Generated) -> Self::Output {
-        for noun in 0..=99 {
-            for verb in 0..=99 {
-                let intcode = start_intcode.clone();
-                if intcode.run_no_io(&[(1, noun), (2, verb)])[0] == 19_690_720 {
-                    return 100 * noun + verb;
-                }
-            }
+    fn part2(program: Self::Generated) -> Self::Output {
+        let mut symbolic = program.symbolic(&[(1, "noun"), (2, "verb")]);
+        let solved = symbolic.run().ok().and_then(|()| {
+            symbolic
+                .cell(0)
+                .ok()?
+                .solve(TARGET, &[("noun", 0..=99), ("verb", 0..=99)])
+        });
+        if let Some(values) = solved {
+            return 100 * values[0] + values[1];
         }
 
-        unreachable!()
+        // The program branched on or pointed through the noun or verb.
+        let pairs = (0..=99).into_par_iter().flat_map(|noun| {
+            (0..=99)
+                .into_par_iter()
+                .map(move |verb| Candidate::patches(vec![(1, noun), (2, verb)]))
+        });
+        let found = program
+            .search_first(pairs, |result| result.memory[0] == TARGET)
+            .unwrap();
+        100 * found.patches[0].1 + found.patches[1].1
     }
 }
 
@@ -36,7 +56,7 @@ mod tests {
     #[test]
     fn d2p1() {
         fn test(program: &str, expected_output: &[IntCodeCell]) {
-            let finished_memory = program.parse::<IntCode>().unwrap().run_no_io(&[]);
+            let finished_memory = program.parse::<IntCode>().unwrap().run_no_io().unwrap();
             assert_eq!(finished_memory, expected_output);
         }
 
@@ -65,6 +65,7 @@ fn to_digits(mut val: Num) -> [Digit; 6] {
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
     use super::*;
 
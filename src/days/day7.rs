@@ -1,13 +1,12 @@
+use crate::intcode::network::*;
 use crate::intcode::*;
 use crate::solver::Solver;
-use crossbeam::channel::unbounded;
 use permutohedron::Heap;
-use std::cmp::max;
 
 pub struct Day7 {}
 
 impl Solver<'_> for Day7 {
-    type Generated = IntCode;
+    type Generated = IntCodeBuilder;
     type Output = IntCodeCell;
 
     fn generator(input: &str) -> Self::Generated {
@@ -15,71 +14,40 @@ impl Solver<'_> for Day7 {
     }
 
     fn part1(start_intcode: Self::Generated) -> Self::Output {
-        let mut phases = [0, 1, 2, 3, 4];
-        let mut max_signal = 0;
-
-        for settings in Heap::new(&mut phases) {
-            let mut signal = 0;
-
-            for phase in &settings {
-                let intcode = start_intcode.clone();
-                let output = intcode.run_with_input(&[*phase, signal]);
-                signal = output[0];
-            }
-
-            max_signal = max(max_signal, signal);
-        }
+        let (_, max_signal) = search::max_by_key(permutations([0, 1, 2, 3, 4]), |settings| {
+            settings.iter().try_fold(0, |signal, &phase| {
+                let amplifier = start_intcode.clone().input(phase).input(signal);
+                Some(amplifier.run_memoized().ok()?.outputs[0])
+            })
+        })
+        .unwrap();
 
         max_signal
     }
 
     fn part2(start_intcode: Self::Generated) -> Self::Output {
-        let mut phases = [5, 6, 7, 8, 9];
-        let mut max_signal = 0;
-        let channels = [
-            unbounded(),
-            unbounded(),
-            unbounded(),
-            unbounded(),
-            unbounded(),
-        ];
-        let threads = rayon::ThreadPoolBuilder::new()
-            .num_threads(phases.len())
-            .build()
-            .unwrap();
-
-        for settings in Heap::new(&mut phases) {
-            assert!(channels.iter().all(|x| x.0.is_empty() && x.1.is_empty()));
-            let wg = crossbeam::sync::WaitGroup::new();
+        let (_, max_signal) = search::max_by_key(permutations([5, 6, 7, 8, 9]), |settings| {
+            let amplifiers = settings
+                .iter()
+                .map(|&phase| start_intcode.clone().input(phase).build())
+                .collect();
 
-            for (index, phase) in settings.iter().enumerate() {
-                let intcode = start_intcode.clone();
-                let wg = wg.clone();
+            let mut network = Network::ring(amplifiers);
+            network.node_mut(0).provide_input(0);
+            assert_eq!(network.run(&mut ()).unwrap(), NetworkStop::Halted);
 
-                let (input_send, input_recv) = channels[index].clone();
-                input_send.send(*phase).unwrap();
-                let (output_send, _) = if index == channels.len() - 1 {
-                    channels[0].clone()
-                } else {
-                    channels[index + 1].clone()
-                };
-
-                threads.spawn(move || {
-                    intcode.run_with_channels(input_recv, output_send);
-                    wg.wait();
-                });
-            }
-
-            channels[0].0.send(0).unwrap();
-            wg.wait();
-            let signal = channels[0].1.recv().unwrap();
-            max_signal = max(max_signal, signal);
-        }
+            network.node(0).pending_inputs().last()
+        })
+        .unwrap();
 
         max_signal
     }
 }
 
+fn permutations(mut phases: [IntCodeCell; 5]) -> Vec<[IntCodeCell; 5]> {
+    Heap::new(&mut phases).collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
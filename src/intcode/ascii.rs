@@ -0,0 +1,203 @@
+//! Driving programs that talk in ASCII text.
+
+use super::*;
+
+/// Something an ASCII program did.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AsciiEvent {
+    /// A line of text, without its newline.
+    Line(String),
+    /// An output outside the ASCII range, such as a puzzle answer.
+    Value(IntCodeCell),
+    /// The program is waiting for input.
+    NeedsInput,
+    /// The program halted.
+    Halted,
+}
+
+/// Everything a program printed before it stopped.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Transcript {
+    /// The text, newlines included.
+    pub text: String,
+    /// Outputs outside the ASCII range, in order.
+    pub values: Vec<IntCodeCell>,
+    /// Whether it stopped by halting, rather than to wait for input.
+    pub halted: bool,
+}
+
+/// A machine that reads and writes text, one character per cell.
+pub struct Ascii {
+    intcode: IntCode,
+    line: String,
+}
+
+impl Ascii {
+    /// Wraps a machine, which carries on from where it is.
+    pub fn new(intcode: IntCode) -> Self {
+        Self {
+            intcode,
+            line: String::new(),
+        }
+    }
+
+    /// The machine being driven.
+    pub fn intcode(&self) -> &IntCode {
+        &self.intcode
+    }
+
+    /// The machine being driven, to queue other input or change limits.
+    pub fn intcode_mut(&mut self) -> &mut IntCode {
+        &mut self.intcode
+    }
+
+    /// Gives the machine back. Text from a line that hasn't ended is lost.
+    pub fn into_inner(self) -> IntCode {
+        self.intcode
+    }
+
+    /// Queues `text` as input, one byte per cell.
+    pub fn send_str(&mut self, text: &str) {
+        for byte in text.bytes() {
+            self.intcode.provide_input(byte.into());
+        }
+    }
+
+    /// Queues `line` as input, followed by a newline.
+    pub fn send_line(&mut self, line: &str) {
+        self.send_str(line);
+        self.intcode.provide_input(b'\n'.into());
+    }
+
+    /// Runs until the next line, value, read or halt.
+    ///
+    /// A partial line is flushed as its own event when the program blocks or halts,
+    /// so prompts without a trailing newline still show up.
+    pub fn next_event(&mut self) -> Result<AsciiEvent, IntCodeError> {
+        loop {
+            let event = match self.intcode.resume()? {
+                StepResult::Output(value) => match ascii_char(value) {
+                    Some('\n') => return Ok(AsciiEvent::Line(std::mem::take(&mut self.line))),
+                    Some(c) => {
+                        self.line.push(c);
+                        continue;
+                    }
+                    None => return Ok(AsciiEvent::Value(value)),
+                },
+                StepResult::NeedsInput => AsciiEvent::NeedsInput,
+                StepResult::Halted => AsciiEvent::Halted,
+                StepResult::Interrupted(interrupt) => {
+                    return Err(self.intcode.interrupted(interrupt))
+                }
+            };
+
+            if !self.line.is_empty() {
+                // Blocking didn't consume anything, so the next call reports it again.
+                return Ok(AsciiEvent::Line(std::mem::take(&mut self.line)));
+            }
+            return Ok(event);
+        }
+    }
+
+    /// Runs until the program needs input or halts, collecting everything it printed.
+    pub fn transcript(&mut self) -> Result<Transcript, IntCodeError> {
+        let mut transcript = Transcript {
+            text: std::mem::take(&mut self.line),
+            ..Transcript::default()
+        };
+
+        loop {
+            match self.intcode.resume()? {
+                StepResult::Output(value) => match ascii_char(value) {
+                    Some(c) => transcript.text.push(c),
+                    None => transcript.values.push(value),
+                },
+                StepResult::NeedsInput => break,
+                StepResult::Halted => {
+                    transcript.halted = true;
+                    break;
+                }
+                StepResult::Interrupted(interrupt) => {
+                    return Err(self.intcode.interrupted(interrupt))
+                }
+            }
+        }
+
+        Ok(transcript)
+    }
+}
+
+pub(super) fn ascii_char(value: IntCodeCell) -> Option<char> {
+    if (0..=127).contains(&value) {
+        Some(value as u8 as char)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events() {
+        let mut ascii = Ascii::new(
+            IntCode::assemble(
+                "
+                    out   #1000
+                    out   #111
+                    out   #107
+                    out   #10
+                    out   #62
+                loop:
+                    in    [c]
+                    eq    [c], #10, [done]
+                    jt    [done], #end
+                    out   [c]
+                    jt    #1, #loop
+                end:
+                    hlt
+                c:    .data 0
+                done: .data 0
+                ",
+            )
+            .unwrap(),
+        );
+
+        assert_eq!(ascii.next_event(), Ok(AsciiEvent::Value(1000)));
+        assert_eq!(ascii.next_event(), Ok(AsciiEvent::Line("ok".to_string())));
+        assert_eq!(ascii.next_event(), Ok(AsciiEvent::Line(">".to_string())));
+        assert_eq!(ascii.next_event(), Ok(AsciiEvent::NeedsInput));
+
+        ascii.send_line("hi");
+        assert_eq!(ascii.next_event(), Ok(AsciiEvent::Line("hi".to_string())));
+        assert_eq!(ascii.next_event(), Ok(AsciiEvent::Halted));
+    }
+
+    #[test]
+    fn transcript() {
+        let mut ascii = Ascii::new(
+            "104,1000,104,111,104,107,104,10,3,0,104,33,99"
+                .parse()
+                .unwrap(),
+        );
+        assert_eq!(
+            ascii.transcript(),
+            Ok(Transcript {
+                text: "ok\n".to_string(),
+                values: vec![1000],
+                halted: false,
+            })
+        );
+
+        ascii.send_str("x");
+        assert_eq!(
+            ascii.transcript(),
+            Ok(Transcript {
+                text: "!".to_string(),
+                values: vec![],
+                halted: true,
+            })
+        );
+    }
+}
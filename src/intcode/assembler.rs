@@ -0,0 +1,589 @@
+//! Assembling programs from mnemonics, labels and addressing mode prefixes.
+
+use super::*;
+use std::collections::HashMap;
+
+const OPCODES: [Opcode; 10] = [
+    Add,
+    Multiply,
+    Input,
+    Output,
+    JumpIfTrue,
+    JumpIfFalse,
+    LessThan,
+    Equals,
+    AdjustRelativeBase,
+    Terminate,
+];
+
+/// Why a source couldn't be assembled. Every variant has the 1-based `line` the
+/// problem is on.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+#[allow(missing_docs)]
+pub enum AssembleError {
+    /// Neither an opcode's mnemonic nor `.data`.
+    UnknownMnemonic { line: usize, mnemonic: String },
+    /// An operand that isn't `[x]`, `#x` or `rb+x`, or a value that isn't a number
+    /// or a label.
+    BadOperand { line: usize, operand: String },
+    /// The opcode takes `expected` operands but was given `found`.
+    WrongOperandCount {
+        line: usize,
+        expected: usize,
+        found: usize,
+    },
+    /// A label defined a second time.
+    DuplicateLabel { line: usize, label: String },
+    /// A label used but never defined.
+    UndefinedLabel { line: usize, label: String },
+    /// A line annotated with address `found` assembles to `expected` instead.
+    AddressMismatch {
+        line: usize,
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AssembleError::UnknownMnemonic { line, mnemonic } => {
+                write!(f, "line {}: unknown mnemonic {:?}", line, mnemonic)
+            }
+            AssembleError::BadOperand { line, operand } => {
+                write!(f, "line {}: bad operand {:?}", line, operand)
+            }
+            AssembleError::WrongOperandCount {
+                line,
+                expected,
+                found,
+            } => write!(
+                f,
+                "line {}: expected {} operands, found {}",
+                line, expected, found
+            ),
+            AssembleError::DuplicateLabel { line, label } => {
+                write!(f, "line {}: duplicate label {:?}", line, label)
+            }
+            AssembleError::UndefinedLabel { line, label } => {
+                write!(f, "line {}: undefined label {:?}", line, label)
+            }
+            AssembleError::AddressMismatch {
+                line,
+                expected,
+                found,
+            } => write!(
+                f,
+                "line {}: annotated address {} but assembling at {}",
+                line, found, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for AssembleError {}
+
+enum Statement<'a> {
+    Instruction(Opcode, Vec<(Mode, Expr<'a>)>),
+    Data(Vec<Expr<'a>>),
+}
+
+impl Statement<'_> {
+    fn len(&self) -> usize {
+        match self {
+            Statement::Instruction(opcode, _) => opcode.parameters() + 1,
+            Statement::Data(values) => values.len(),
+        }
+    }
+}
+
+struct Expr<'a> {
+    label: Option<&'a str>,
+    offset: IntCodeCell,
+}
+
+impl<'a> Expr<'a> {
+    fn parse(s: &'a str) -> Option<Self> {
+        let s = s.trim();
+        if let Ok(offset) = s.parse() {
+            return Some(Self {
+                label: None,
+                offset,
+            });
+        }
+
+        let (label, offset) = match s.find(['+', '-']) {
+            Some(split) => (s[..split].trim(), s[split..].replace(' ', "").parse().ok()?),
+            None => (s, 0),
+        };
+
+        if is_label(label) {
+            Some(Self {
+                label: Some(label),
+                offset,
+            })
+        } else {
+            None
+        }
+    }
+
+    fn resolve(
+        &self,
+        line: usize,
+        labels: &HashMap<&str, usize>,
+    ) -> Result<IntCodeCell, AssembleError> {
+        match self.label {
+            Some(label) => match labels.get(label) {
+                Some(&address) => Ok(address as IntCodeCell + self.offset),
+                None => Err(AssembleError::UndefinedLabel {
+                    line,
+                    label: label.to_string(),
+                }),
+            },
+            None => Ok(self.offset),
+        }
+    }
+}
+
+fn is_label(s: &str) -> bool {
+    s != "rb"
+        && s.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn parse_operand(line: usize, operand: &str) -> Result<(Mode, Expr<'_>), AssembleError> {
+    let operand = operand.trim();
+    let bad_operand = || AssembleError::BadOperand {
+        line,
+        operand: operand.to_string(),
+    };
+
+    let parsed = if operand.starts_with('[') && operand.ends_with(']') {
+        Expr::parse(&operand[1..operand.len() - 1]).map(|e| (Position, e))
+    } else if let Some(rest) = operand.strip_prefix('#') {
+        Expr::parse(rest).map(|e| (Immediate, e))
+    } else if operand == "rb" {
+        Some((
+            Relative,
+            Expr {
+                label: None,
+                offset: 0,
+            },
+        ))
+    } else if let Some(rest) = operand.strip_prefix("rb") {
+        let rest = rest.trim_start();
+        // `rb+label` offsets by a label's address, but a label can't be negated.
+        match rest.strip_prefix('+') {
+            Some(offset) => Expr::parse(offset),
+            None if rest.starts_with('-') => Expr::parse(rest),
+            None => None,
+        }
+        .map(|e| (Relative, e))
+    } else {
+        None
+    };
+
+    parsed.ok_or_else(bad_operand)
+}
+
+fn parse_statement(line: usize, text: &str) -> Result<Option<Statement<'_>>, AssembleError> {
+    let (mnemonic, rest) = match text.find(char::is_whitespace) {
+        Some(split) => (&text[..split], text[split..].trim()),
+        None => (text, ""),
+    };
+    let operands = if rest.is_empty() {
+        Vec::new()
+    } else {
+        rest.split(',').collect()
+    };
+
+    if mnemonic.is_empty() {
+        return Ok(None);
+    }
+
+    if mnemonic == ".data" {
+        return operands
+            .into_iter()
+            .map(|value| {
+                Expr::parse(value).ok_or_else(|| AssembleError::BadOperand {
+                    line,
+                    operand: value.trim().to_string(),
+                })
+            })
+            .collect::<Result<_, _>>()
+            .map(|values| Some(Statement::Data(values)));
+    }
+
+    let opcode = OPCODES
+        .iter()
+        .copied()
+        .find(|opcode| opcode.mnemonic() == mnemonic)
+        .ok_or_else(|| AssembleError::UnknownMnemonic {
+            line,
+            mnemonic: mnemonic.to_string(),
+        })?;
+
+    if operands.len() != opcode.parameters() {
+        return Err(AssembleError::WrongOperandCount {
+            line,
+            expected: opcode.parameters(),
+            found: operands.len(),
+        });
+    }
+
+    Ok(Some(Statement::Instruction(
+        opcode,
+        operands
+            .into_iter()
+            .map(|operand| parse_operand(line, operand))
+            .collect::<Result<_, _>>()?,
+    )))
+}
+
+/// Assembles `source` into program memory.
+///
+/// Each line holds an instruction such as `add [a], #1, rb-2`, or `.data` followed
+/// by values. Operands are `[x]` in position mode, `#x` in immediate mode and
+/// `rb+x` in relative mode, where `x` is a number, a label or a label plus or
+/// minus a number; negative numbers can also be written `rb-5`. A line can start
+/// with `name:` to define a label there, or with its address, as in `12:`, which
+/// is checked. Comments run from `;` to the end of the line.
+pub fn assemble(source: &str) -> Result<Vec<IntCodeCell>, AssembleError> {
+    let mut labels = HashMap::new();
+    let mut statements = Vec::new();
+    let mut address = 0;
+
+    for (line, text) in source.lines().enumerate() {
+        let line = line + 1;
+        let mut text = text.split(';').next().unwrap().trim();
+
+        while let Some(colon) = text.find(':') {
+            let name = text[..colon].trim();
+
+            if let Ok(found) = name.parse::<usize>() {
+                if found != address {
+                    return Err(AssembleError::AddressMismatch {
+                        line,
+                        expected: address,
+                        found,
+                    });
+                }
+            } else if is_label(name) {
+                if labels.insert(name, address).is_some() {
+                    return Err(AssembleError::DuplicateLabel {
+                        line,
+                        label: name.to_string(),
+                    });
+                }
+            } else {
+                break;
+            }
+
+            text = text[colon + 1..].trim();
+        }
+
+        if let Some(statement) = parse_statement(line, text)? {
+            address += statement.len();
+            statements.push((line, statement));
+        }
+    }
+
+    let mut program = Vec::with_capacity(address);
+    for (line, statement) in statements {
+        match statement {
+            Statement::Instruction(opcode, operands) => {
+                let modes = operands.iter().map(|&(mode, _)| mode).collect::<Vec<_>>();
+                program.push(Instruction::encode(opcode, &modes));
+
+                for (_, expr) in operands {
+                    program.push(expr.resolve(line, &labels)?);
+                }
+            }
+            Statement::Data(values) => {
+                for expr in values {
+                    program.push(expr.resolve(line, &labels)?);
+                }
+            }
+        }
+    }
+
+    Ok(program)
+}
+
+impl IntCode {
+    /// A machine loaded with the assembled program. See [`assemble`].
+    pub fn assemble(source: &str) -> Result<Self, AssembleError> {
+        Ok(Self::new(assemble(source)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assemble_text(source: &str) -> String {
+        IntCode::assemble(source).unwrap().to_string()
+    }
+
+    #[test]
+    fn programs() {
+        assert_eq!(
+            assemble_text(
+                "
+                    in    [input]
+                    eq    [input], [eight], [input]
+                    out   [input]
+                    hlt
+                input: .data -1
+                eight: .data 8
+                "
+            ),
+            "3,9,8,9,10,9,4,9,99,-1,8"
+        );
+
+        assert_eq!(
+            assemble_text(
+                "
+                    in    [phase]
+                    in    [signal]
+                    mul   [signal], #10, [signal]
+                    add   [signal], [phase], [phase]
+                    out   [phase]
+                    hlt
+                phase:  .data 0
+                signal: .data 0
+                "
+            ),
+            "3,15,3,16,1002,16,10,16,1,16,15,15,4,15,99,0,0"
+        );
+
+        assert_eq!(
+            assemble_text(
+                "
+                start:
+                    arb   #1
+                    out   rb-1
+                    add   [100], #1, [100]
+                    eq    [100], #16, [101]
+                    jf    [101], #start
+                    hlt
+                "
+            ),
+            "109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99"
+        );
+
+        // Relative operands that name labels, for tables indexed by the relative base.
+        assert_eq!(
+            assemble_text(
+                "
+                    arb   #1
+                    out   rb+table
+                    out   rb + table+1
+                    out   rb-1
+                    hlt
+                table: .data 10, 20, 30
+                "
+            ),
+            "109,1,204,9,204,10,204,-1,99,10,20,30"
+        );
+        assert_eq!(
+            IntCode::assemble("arb #1\nout rb+table\nhlt\ntable: .data 10, 20")
+                .unwrap()
+                .run_with_input(&[]),
+            Ok(vec![20])
+        );
+    }
+
+    // Puzzle examples written out in assembly, which should encode to exactly the
+    // published programs.
+    #[test]
+    fn puzzle_examples() {
+        assert_eq!(
+            assemble_text(
+                "
+                    add   [a], [b], [3]     ; overwrites its own output address
+                    mul   [3], [c], [0]
+                    hlt
+                a:  .data 30
+                b:  .data 40
+                c:  .data 50
+                "
+            ),
+            "1,9,10,3,2,3,11,0,99,30,40,50"
+        );
+
+        assert_eq!(
+            assemble_text(
+                "
+                    add   [1], [1], [patched]
+                patched:
+                    hlt                     ; becomes mul [5], [6], [0]
+                    .data 5, 6, 0
+                    hlt
+                "
+            ),
+            "1,1,1,4,99,5,6,0,99"
+        );
+
+        assert_eq!(
+            assemble_text(
+                "
+                    in    [compare+1]
+                compare:
+                    lt    #-1, #8, [compare+1]
+                    out   [compare+1]
+                    hlt
+                "
+            ),
+            "3,3,1107,-1,8,3,4,3,99"
+        );
+
+        assert_eq!(
+            assemble_text(
+                "
+                    in    [input]
+                    eq    [input], #8, [flag]
+                    jt    [flag], #equal
+                    lt    #8, [input], [flag]
+                    jf    [flag], #less
+                    jf    #0, #greater
+                    .data 98
+                flag:  .data 0
+                input: .data 0
+                equal:
+                    mul   [input], #125, [flag]
+                    out   [flag]
+                    jt    #1, #done
+                less:
+                    out   #999
+                    jt    #1, #done
+                greater:
+                    add   #1000, #1, [flag]
+                    out   [flag]
+                    jt    #1, #done
+                    .data 98
+                done:
+                    hlt
+                "
+            ),
+            "3,21,1008,21,8,20,1005,20,22,107,8,21,20,1006,20,31,1106,0,36,98,0,0,1002,21,125,20,4,20,1105,1,46,104,999,1105,1,46,1101,1000,1,20,4,20,1105,1,46,98,99"
+        );
+
+        assert_eq!(
+            assemble_text(
+                "
+                    in    [phase]
+                    add   [phase], #-5, [phase]
+                loop:
+                    in    [signal]
+                    add   [phase], [count], [value]
+                wrap:
+                    lt    [value], #5, [flag]
+                    jt    [flag], #add_it
+                    add   [value], #-5, [value]
+                    jt    #1, #wrap
+                add_it:
+                    add   [signal], [value], [signal]
+                    eq    [value], #0, [flag]
+                    add   [flag], #1, [flag]
+                    mul   [signal], [flag], [signal]
+                    out   [signal]
+                    add   [count], #-1, [count]
+                    jt    [count], #loop
+                    hlt
+                phase:  .data 0
+                signal: .data 0
+                value:  .data 0
+                flag:   .data 0
+                count:  .data 10
+                "
+            ),
+            "3,52,1001,52,-5,52,3,53,1,52,56,54,1007,54,5,55,1005,55,26,1001,54,-5,54,1105,1,12,1,53,54,53,1008,54,0,55,1001,55,1,55,2,53,55,53,4,53,1001,56,-1,56,1005,56,6,99,0,0,0,0,10"
+        );
+
+        assert_eq!(
+            assemble_text(
+                "
+                    mul   #34915192, #34915192, [result]
+                    out   [result]
+                    hlt
+                result: .data 0
+                "
+            ),
+            "1102,34915192,34915192,7,4,7,99,0"
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            assemble("  add [1], #2"),
+            Err(AssembleError::WrongOperandCount {
+                line: 1,
+                expected: 3,
+                found: 2
+            })
+        );
+        assert_eq!(
+            assemble("\nfoo [1]"),
+            Err(AssembleError::UnknownMnemonic {
+                line: 2,
+                mnemonic: "foo".to_string()
+            })
+        );
+        assert_eq!(
+            assemble("out 5"),
+            Err(AssembleError::BadOperand {
+                line: 1,
+                operand: "5".to_string()
+            })
+        );
+        assert_eq!(
+            assemble("out rb-label"),
+            Err(AssembleError::BadOperand {
+                line: 1,
+                operand: "rb-label".to_string()
+            })
+        );
+        assert_eq!(
+            assemble("jt #1, #nowhere"),
+            Err(AssembleError::UndefinedLabel {
+                line: 1,
+                label: "nowhere".to_string()
+            })
+        );
+        assert_eq!(
+            assemble("a: hlt\na: hlt"),
+            Err(AssembleError::DuplicateLabel {
+                line: 2,
+                label: "a".to_string()
+            })
+        );
+        assert_eq!(
+            assemble("0: hlt\n2: hlt"),
+            Err(AssembleError::AddressMismatch {
+                line: 2,
+                expected: 1,
+                found: 2
+            })
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        for program in &[
+            include_str!("../../input/2019/day9.txt"),
+            include_str!("../../input/2019/day13.txt"),
+            include_str!("../../input/2019/day15.txt"),
+            include_str!("../../input/2019/day17.txt"),
+        ] {
+            let intcode = program.trim().parse::<IntCode>().unwrap();
+            let listing = intcode.disassemble().to_string();
+            assert_eq!(
+                IntCode::assemble(&listing).unwrap().to_string(),
+                program.trim()
+            );
+        }
+    }
+}
@@ -0,0 +1,431 @@
+//! Running machines as futures on a single threaded executor.
+
+use super::*;
+use std::cell::RefCell;
+use std::future::{poll_fn, Future};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+
+// How many instructions a machine runs before letting other tasks have a turn.
+const YIELD_INTERVAL: u64 = 10_000;
+
+/// Where a machine run with `run_async` gets its input.
+pub trait AsyncInput {
+    /// The next value, if there is one yet. `Ready(None)` means the input will never
+    /// produce anything again.
+    fn poll_recv(&mut self, cx: &mut Context) -> Poll<Option<IntCodeCell>>;
+}
+
+/// Where a machine run with `run_async` sends its output.
+pub trait AsyncOutput {
+    /// Sends `value`, or says that nothing will ever read it.
+    fn poll_send(&mut self, cx: &mut Context, value: IntCodeCell) -> Poll<Result<(), Closed>>;
+}
+
+/// The other end of a channel has been dropped.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Closed;
+
+struct Shared {
+    queue: VecDeque<IntCodeCell>,
+    wakers: Vec<Waker>,
+    senders: usize,
+    receivers: usize,
+}
+
+impl Shared {
+    fn wake(&mut self) {
+        self.wakers.drain(..).for_each(Waker::wake);
+    }
+}
+
+/// Unbounded single-threaded channel for machines sharing a `LocalExecutor`.
+pub fn channel() -> (AsyncSender, AsyncReceiver) {
+    let shared = Rc::new(RefCell::new(Shared {
+        queue: VecDeque::new(),
+        wakers: Vec::new(),
+        senders: 1,
+        receivers: 1,
+    }));
+    (AsyncSender(shared.clone()), AsyncReceiver(shared))
+}
+
+/// The sending half of a [`channel`]. Clones send to the same receivers.
+pub struct AsyncSender(Rc<RefCell<Shared>>);
+
+impl AsyncSender {
+    /// Queues `value` without waiting, unless every receiver is gone.
+    pub fn send(&self, value: IntCodeCell) -> Result<(), Closed> {
+        let mut shared = self.0.borrow_mut();
+        if shared.receivers == 0 {
+            return Err(Closed);
+        }
+
+        shared.queue.push_back(value);
+        shared.wake();
+        Ok(())
+    }
+}
+
+impl Clone for AsyncSender {
+    fn clone(&self) -> Self {
+        self.0.borrow_mut().senders += 1;
+        Self(self.0.clone())
+    }
+}
+
+impl Drop for AsyncSender {
+    fn drop(&mut self) {
+        let mut shared = self.0.borrow_mut();
+        shared.senders -= 1;
+        if shared.senders == 0 {
+            shared.wake();
+        }
+    }
+}
+
+impl AsyncOutput for AsyncSender {
+    fn poll_send(&mut self, _: &mut Context, value: IntCodeCell) -> Poll<Result<(), Closed>> {
+        Poll::Ready(self.send(value))
+    }
+}
+
+/// The receiving half of a [`channel`]. Clones share one queue, so each value goes
+/// to only one of them.
+pub struct AsyncReceiver(Rc<RefCell<Shared>>);
+
+impl AsyncReceiver {
+    /// The next value if one is queued, without waiting.
+    pub fn try_recv(&self) -> Option<IntCodeCell> {
+        self.0.borrow_mut().queue.pop_front()
+    }
+}
+
+impl Clone for AsyncReceiver {
+    fn clone(&self) -> Self {
+        self.0.borrow_mut().receivers += 1;
+        Self(self.0.clone())
+    }
+}
+
+impl Drop for AsyncReceiver {
+    fn drop(&mut self) {
+        self.0.borrow_mut().receivers -= 1;
+    }
+}
+
+impl AsyncInput for AsyncReceiver {
+    fn poll_recv(&mut self, cx: &mut Context) -> Poll<Option<IntCodeCell>> {
+        let mut shared = self.0.borrow_mut();
+        match shared.queue.pop_front() {
+            Some(value) => Poll::Ready(Some(value)),
+            None if shared.senders == 0 => Poll::Ready(None),
+            None => {
+                // A task polling again while it waits would otherwise pile up wakers.
+                if !shared
+                    .wakers
+                    .iter()
+                    .any(|waker| waker.will_wake(cx.waker()))
+                {
+                    shared.wakers.push(cx.waker().clone());
+                }
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl IntCode {
+    /// Runs to completion as a future, waiting on `input` whenever the program reads.
+    /// Fails with `ChannelClosed` if either end is closed while it's needed. Long
+    /// stretches of computation yield every so often, so other tasks still get to run.
+    pub async fn run_async(
+        mut self,
+        mut input: impl AsyncInput,
+        mut output: impl AsyncOutput,
+    ) -> Result<(), IntCodeError> {
+        loop {
+            let result = match self.resume_for(YIELD_INTERVAL)? {
+                Some(result) => result,
+                None => {
+                    yield_now().await;
+                    continue;
+                }
+            };
+
+            match result {
+                StepResult::NeedsInput => match poll_fn(|cx| input.poll_recv(cx)).await {
+                    Some(value) => self.provide_input(value),
+                    None => return Err(self.channel_closed(self.pc)),
+                },
+                StepResult::Output(value) => {
+                    if poll_fn(|cx| output.poll_send(cx, value)).await.is_err() {
+                        // The output instruction has already been stepped over.
+                        return Err(self.channel_closed(self.pc - 2));
+                    }
+                }
+                StepResult::Halted => return Ok(()),
+                StepResult::Interrupted(interrupt) => return Err(self.interrupted(interrupt)),
+            }
+        }
+    }
+
+    // Like `resume`, but returns `None` after `slice` instructions. The machine's own
+    // budget, if it has one, is spent as usual.
+    fn resume_for(&mut self, slice: u64) -> Result<Option<StepResult>, IntCodeError> {
+        let budget = self.limits.instruction_budget;
+        let slice = budget.map_or(slice, |budget| budget.min(slice));
+
+        self.limits.instruction_budget = Some(slice);
+        let result = self.resume();
+        let spent = slice - self.limits.instruction_budget.unwrap_or(0);
+        self.limits.instruction_budget = budget.map(|budget| budget - spent);
+
+        match result? {
+            StepResult::Interrupted(Interrupt::BudgetExhausted)
+                if self.limits.instruction_budget != Some(0) =>
+            {
+                Ok(None)
+            }
+            result => Ok(Some(result)),
+        }
+    }
+}
+
+// Lets every other ready task run before carrying on.
+async fn yield_now() {
+    let mut yielded = false;
+    poll_fn(|cx| {
+        if yielded {
+            Poll::Ready(())
+        } else {
+            yielded = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    })
+    .await
+}
+
+type Task = Pin<Box<dyn Future<Output = ()>>>;
+
+struct TaskWaker {
+    id: usize,
+    ready: Arc<Mutex<VecDeque<usize>>>,
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.ready.lock().unwrap().push_back(self.id);
+    }
+}
+
+/// The result of a task spawned on a [`LocalExecutor`].
+pub struct JoinHandle<T>(Rc<RefCell<Option<T>>>);
+
+impl<T> JoinHandle<T> {
+    /// `None` until the task has finished, and after the result has been taken.
+    pub fn take(&self) -> Option<T> {
+        self.0.borrow_mut().take()
+    }
+}
+
+/// Polls every task on the calling thread; nothing here needs to be `Send`.
+#[derive(Default)]
+pub struct LocalExecutor {
+    // Each task keeps the same waker, so wakers can tell when they'd wake the same task.
+    tasks: Vec<Option<(Task, Waker)>>,
+    ready: Arc<Mutex<VecDeque<usize>>>,
+}
+
+impl LocalExecutor {
+    /// An executor with no tasks.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a task. Nothing runs until [`run`](Self::run) is called.
+    pub fn spawn<F: Future + 'static>(&mut self, future: F) -> JoinHandle<F::Output> {
+        let result = Rc::new(RefCell::new(None));
+        let handle = JoinHandle(result.clone());
+
+        let id = self.tasks.len();
+        let waker = Waker::from(Arc::new(TaskWaker {
+            id,
+            ready: self.ready.clone(),
+        }));
+        let task: Task = Box::pin(async move {
+            let output = future.await;
+            *result.borrow_mut() = Some(output);
+        });
+        self.ready.lock().unwrap().push_back(id);
+        self.tasks.push(Some((task, waker)));
+
+        handle
+    }
+
+    /// Runs until no task can make progress, returning how many are still unfinished.
+    pub fn run(&mut self) -> usize {
+        loop {
+            let id = match self.ready.lock().unwrap().pop_front() {
+                Some(id) => id,
+                None => break,
+            };
+
+            if let Some((task, waker)) = &mut self.tasks[id] {
+                if task
+                    .as_mut()
+                    .poll(&mut Context::from_waker(waker))
+                    .is_ready()
+                {
+                    self.tasks[id] = None;
+                }
+            }
+        }
+
+        self.tasks.iter().filter(|task| task.is_some()).count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn amplifiers() {
+        let intcode =
+            "3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5"
+                .parse::<IntCode>()
+                .unwrap();
+        let mut executor = LocalExecutor::new();
+        let (senders, receivers): (Vec<_>, Vec<_>) = (0..5).map(|_| channel()).unzip();
+
+        let handles = [9, 8, 7, 6, 5]
+            .iter()
+            .enumerate()
+            .map(|(index, &phase)| {
+                senders[index].send(phase).unwrap();
+                let machine = intcode.clone().run_async(
+                    receivers[index].clone(),
+                    senders[(index + 1) % senders.len()].clone(),
+                );
+                executor.spawn(machine)
+            })
+            .collect::<Vec<_>>();
+        senders[0].send(0).unwrap();
+
+        assert_eq!(executor.run(), 0);
+        assert!(handles.iter().all(|handle| handle.take() == Some(Ok(()))));
+        assert_eq!(receivers[0].try_recv(), Some(139_629_729));
+    }
+
+    #[test]
+    fn many_machines() {
+        let increment = "3,0,1001,0,1,0,4,0,99".parse::<IntCode>().unwrap();
+        let mut executor = LocalExecutor::new();
+        let (first, mut input) = channel();
+
+        for _ in 0..5000 {
+            let (output, next_input) = channel();
+            executor.spawn(increment.clone().run_async(input, output));
+            input = next_input;
+        }
+
+        assert_eq!(executor.run(), 5000);
+        first.send(0).unwrap();
+        assert_eq!(executor.run(), 0);
+        assert_eq!(input.try_recv(), Some(5000));
+    }
+
+    #[test]
+    fn yields() {
+        // Counts down from its input, then outputs zero.
+        let countdown = IntCode::assemble(
+            "
+                in    [count]
+            loop:
+                add   [count], #-1, [count]
+                jt    [count], #loop
+                out   [count]
+                hlt
+            count: .data 0
+            ",
+        )
+        .unwrap();
+        let mut executor = LocalExecutor::new();
+        let finished = Rc::new(RefCell::new(Vec::new()));
+
+        let (sender, receiver) = channel();
+        let (output, _outputs) = channel();
+        sender.send(100_000).unwrap();
+        let machine = countdown.clone().run_async(receiver, output);
+        let log = finished.clone();
+        executor.spawn(async move {
+            machine.await.unwrap();
+            log.borrow_mut().push("machine");
+        });
+        let log = finished.clone();
+        executor.spawn(async move { log.borrow_mut().push("other") });
+
+        assert_eq!(executor.run(), 0);
+        assert_eq!(*finished.borrow(), vec!["other", "machine"]);
+
+        // The machine's own budget still runs out, across however many yields.
+        let mut limited = countdown;
+        limited.set_run_limits(RunLimits::default().with_instruction_budget(25_000));
+        let (sender, receiver) = channel();
+        let (output, _outputs) = channel();
+        sender.send(100_000).unwrap();
+        let handle = executor.spawn(limited.run_async(receiver, output));
+
+        assert_eq!(executor.run(), 0);
+        assert_eq!(
+            handle.take(),
+            Some(Err(IntCodeError::Interrupted {
+                pc: 6,
+                instruction: 1005,
+                interrupt: Interrupt::BudgetExhausted
+            }))
+        );
+    }
+
+    #[test]
+    fn one_waker_per_task() {
+        let (_sender, mut receiver) = channel();
+        let waker = Waker::from(Arc::new(TaskWaker {
+            id: 0,
+            ready: Arc::default(),
+        }));
+        let mut cx = Context::from_waker(&waker);
+        for _ in 0..3 {
+            assert_eq!(receiver.poll_recv(&mut cx), Poll::Pending);
+        }
+        assert_eq!(receiver.0.borrow().wakers.len(), 1);
+    }
+
+    #[test]
+    fn closed() {
+        let mut executor = LocalExecutor::new();
+        let (sender, receiver) = channel();
+        let (output, _) = channel();
+        let handle = executor.spawn(
+            "3,0,99"
+                .parse::<IntCode>()
+                .unwrap()
+                .run_async(receiver, output),
+        );
+
+        assert_eq!(executor.run(), 1);
+        drop(sender);
+        assert_eq!(executor.run(), 0);
+        assert_eq!(
+            handle.take(),
+            Some(Err(IntCodeError::ChannelClosed {
+                pc: 0,
+                instruction: 3
+            }))
+        );
+    }
+}
@@ -0,0 +1,316 @@
+//! Catching self modifying code, and comparing a machine's memory to another's.
+
+use super::*;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+/// A write to a cell that is, or later becomes, part of an executed instruction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CodeWrite<C = IntCodeCell> {
+    /// The instruction doing the write.
+    pub pc: usize,
+    /// Where it wrote, and what.
+    pub write: MemoryWrite<C>,
+    /// Whether the cell had already been executed, as opposed to only being executed later.
+    pub executed_before: bool,
+}
+
+/// A cell that differs between two machines.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CellChange<C = IntCodeCell> {
+    /// Where the cell is.
+    pub address: usize,
+    /// Its value in the machine compared against.
+    pub original: C,
+    /// Its value in this machine.
+    pub current: C,
+}
+
+/// Watches for writes to any cell that is part of an executed instruction, whether
+/// the write comes before or after that instruction runs.
+pub struct Auditor<C = IntCodeCell> {
+    executed: HashSet<usize>,
+    // The first and latest writes to each cell that hasn't been executed yet, in
+    // case it is later. Data cells can be written millions of times, so the writes
+    // between are dropped.
+    pending: HashMap<usize, (CodeWrite<C>, Option<CodeWrite<C>>)>,
+    code_writes: Vec<CodeWrite<C>>,
+}
+
+impl<C> Default for Auditor<C> {
+    fn default() -> Self {
+        Self {
+            executed: HashSet::new(),
+            pending: HashMap::new(),
+            code_writes: Vec::new(),
+        }
+    }
+}
+
+impl<C: Clone + PartialEq> Auditor<C> {
+    /// An auditor that hasn't seen anything run.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether any instruction run so far covers `address`.
+    pub fn is_executed(&self, address: usize) -> bool {
+        self.executed.contains(&address)
+    }
+
+    /// In the order they were found, which is not always the order they happened.
+    /// Of the writes to a cell before it first runs, only the first and the last are
+    /// kept.
+    pub fn code_writes(&self) -> &[CodeWrite<C>] {
+        &self.code_writes
+    }
+
+    /// Whether the program changed its own code. Rewriting a cell with the value it
+    /// already holds doesn't count.
+    pub fn modifies_code(&self) -> bool {
+        self.code_writes
+            .iter()
+            .any(|code_write| code_write.write.old != code_write.write.new)
+    }
+}
+
+impl<C: Clone> Tracer<C> for Auditor<C> {
+    fn trace(&mut self, event: &TraceEvent<C>) {
+        for address in event.pc..=event.pc + event.opcode.parameters() {
+            if self.executed.insert(address) {
+                if let Some((first, last)) = self.pending.remove(&address) {
+                    self.code_writes.push(first);
+                    self.code_writes.extend(last);
+                }
+            }
+        }
+
+        if let Some(write) = &event.write {
+            let executed_before = self.executed.contains(&write.address);
+            let code_write = CodeWrite {
+                pc: event.pc,
+                write: write.clone(),
+                executed_before,
+            };
+            if executed_before {
+                self.code_writes.push(code_write);
+            } else {
+                match self.pending.entry(write.address) {
+                    Entry::Occupied(mut entry) => entry.get_mut().1 = Some(code_write),
+                    Entry::Vacant(entry) => {
+                        entry.insert((code_write, None));
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<C: Cell> IntCode<C> {
+    /// Every cell whose value differs from `original`, usually a clone taken before
+    /// running.
+    pub fn memory_diff(&self, original: &Self) -> Vec<CellChange<C>> {
+        let program_len = std::cmp::max(
+            self.memory.starting_memory.len(),
+            original.memory.starting_memory.len(),
+        );
+        let extra = original
+            .memory
+            .extra()
+            .chain(self.memory.extra())
+            .map(|(address, _)| address)
+            .filter(|&address| address >= program_len)
+            .collect::<BTreeSet<_>>();
+
+        self.changes((0..program_len).chain(extra), |address| {
+            original.memory[address].clone()
+        })
+    }
+
+    /// Every cell whose value differs from the program the machine was created with,
+    /// or restored from. Patches made through a builder count as changes.
+    pub fn diff_from_start(&self) -> Vec<CellChange<C>> {
+        let start = &self.memory.original;
+        let extra = self
+            .memory
+            .extra()
+            .map(|(address, _)| address)
+            .collect::<BTreeSet<_>>();
+
+        self.changes((0..start.len()).chain(extra), |address| {
+            start.get(address).cloned().unwrap_or_default()
+        })
+    }
+
+    fn changes(
+        &self,
+        addresses: impl Iterator<Item = usize>,
+        original: impl Fn(usize) -> C,
+    ) -> Vec<CellChange<C>> {
+        addresses
+            .filter_map(|address| {
+                let (original, current) = (original(address), &self.memory[address]);
+                if original == *current {
+                    return None;
+                }
+                Some(CellChange {
+                    address,
+                    original,
+                    current: current.clone(),
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn audit(mut intcode: IntCode) -> Auditor {
+        let mut auditor = Auditor::new();
+        while intcode.resume_traced(&mut auditor) != Ok(StepResult::Halted) {}
+        auditor
+    }
+
+    #[test]
+    fn self_modifying() {
+        // Bumps the operand of an `out` it has already run, then writes a `hlt` over
+        // an `add` before reaching it.
+        let intcode = IntCode::assemble(
+            "
+            again:
+                out   #1
+                add   [again+1], #1, [again+1]
+                eq    [again+1], #3, [done]
+                jf    [done], #again
+                mul   #99, #1, [end]
+            end:
+                add   #0, #0, [done]
+            done: .data 0
+            ",
+        )
+        .unwrap();
+        let auditor = audit(intcode);
+
+        assert!(auditor.modifies_code());
+        assert!(auditor.is_executed(17));
+        assert!(!auditor.is_executed(18));
+        assert_eq!(
+            auditor.code_writes(),
+            &[
+                CodeWrite {
+                    pc: 2,
+                    write: MemoryWrite {
+                        address: 1,
+                        old: 1,
+                        new: 2
+                    },
+                    executed_before: true,
+                },
+                CodeWrite {
+                    pc: 2,
+                    write: MemoryWrite {
+                        address: 1,
+                        old: 2,
+                        new: 3
+                    },
+                    executed_before: true,
+                },
+                CodeWrite {
+                    pc: 13,
+                    write: MemoryWrite {
+                        address: 17,
+                        old: 1101,
+                        new: 99
+                    },
+                    executed_before: false,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn repeated_writes() {
+        // Counts a cell up to 99, then runs it as a `hlt`.
+        let intcode = IntCode::assemble(
+            "
+            loop:
+                add   [target], #1, [target]
+                lt    [target], #99, [flag]
+                jt    [flag], #loop
+            target: .data 89
+            flag:   .data 0
+            ",
+        )
+        .unwrap();
+        let auditor = audit(intcode);
+
+        let write = |old, new| CodeWrite {
+            pc: 0,
+            write: MemoryWrite {
+                address: 11,
+                old,
+                new,
+            },
+            executed_before: false,
+        };
+        assert_eq!(auditor.code_writes(), &[write(89, 90), write(98, 99)]);
+
+        // Of the ten writes to `flag`, which never runs, only two are held on to.
+        let (first, last) = &auditor.pending[&12];
+        assert_eq!((first.write.old, first.write.new), (0, 1));
+        assert_eq!(last.as_ref().map(|last| last.write.new), Some(0));
+    }
+
+    #[test]
+    fn puzzle_programs() {
+        let intcode = include_str!("../../input/2019/day9.txt")
+            .trim()
+            .parse::<IntCode>()
+            .unwrap();
+        let mut auditor = Auditor::new();
+        let mut ran = intcode.clone();
+        ran.provide_input(1);
+        while ran.resume_traced(&mut auditor).unwrap() != StepResult::Halted {}
+        assert!(!auditor.modifies_code());
+
+        let diff = ran.memory_diff(&intcode);
+        assert!(!diff.is_empty());
+        assert!(diff
+            .iter()
+            .all(|change| !auditor.is_executed(change.address)));
+    }
+
+    #[test]
+    fn memory_diff() {
+        let original = "1,0,0,0,99".parse::<IntCode>().unwrap();
+        let mut intcode = original.clone();
+        intcode.replace_cell(1, 4);
+        intcode.replace_cell(5000, 7);
+        intcode.resume().unwrap();
+
+        assert_eq!(intcode.diff_from_start(), intcode.memory_diff(&original));
+        assert_eq!(original.diff_from_start(), vec![]);
+        assert_eq!(
+            intcode.memory_diff(&original),
+            vec![
+                CellChange {
+                    address: 0,
+                    original: 1,
+                    current: 100
+                },
+                CellChange {
+                    address: 1,
+                    original: 0,
+                    current: 4
+                },
+                CellChange {
+                    address: 5000,
+                    original: 0,
+                    current: 7
+                },
+            ]
+        );
+    }
+}
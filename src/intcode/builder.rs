@@ -0,0 +1,156 @@
+//! Setting up a machine before it runs.
+
+use super::*;
+
+/// Sets up a machine before it starts running.
+///
+/// ```
+/// use aoc2019::intcode::IntCode;
+///
+/// let outputs = IntCode::builder(vec![1, 0, 0, 0, 4, 0, 99, 40, 2])
+///     .patch(1, 7)
+///     .patch(2, 8)
+///     .build()
+///     .run_with_input(&[])
+///     .unwrap();
+/// assert_eq!(outputs, vec![42]);
+/// ```
+#[derive(Clone, Debug)]
+pub struct IntCodeBuilder<C: Cell = IntCodeCell> {
+    program: Vec<C>,
+    patches: Vec<(usize, C)>,
+    inputs: Vec<C>,
+    engine: Option<Engine>,
+    memory_limits: MemoryLimits,
+    run_limits: RunLimits,
+}
+
+impl<C: Cell> IntCodeBuilder<C> {
+    /// Starts from `program`, unpatched, with no input and no limits.
+    pub fn new(program: Vec<C>) -> Self {
+        Self {
+            program,
+            patches: Vec::new(),
+            inputs: Vec::new(),
+            engine: None,
+            memory_limits: MemoryLimits::default(),
+            run_limits: RunLimits::default(),
+        }
+    }
+
+    /// Overwrites one cell of the program, or of the memory past its end.
+    pub fn patch(mut self, address: usize, value: C) -> Self {
+        self.patches.push((address, value));
+        self
+    }
+
+    /// Queues a value for the program to read.
+    pub fn input(mut self, value: C) -> Self {
+        self.inputs.push(value);
+        self
+    }
+
+    /// Queues several values for the program to read, in order.
+    pub fn inputs(mut self, values: impl IntoIterator<Item = C>) -> Self {
+        self.inputs.extend(values);
+        self
+    }
+
+    /// Defaults to whatever `Engine::set_default` last chose.
+    pub fn engine(mut self, engine: Engine) -> Self {
+        self.engine = Some(engine);
+        self
+    }
+
+    /// Defaults to no limits.
+    pub fn memory_limits(mut self, limits: MemoryLimits) -> Self {
+        self.memory_limits = limits;
+        self
+    }
+
+    /// Defaults to no limits.
+    pub fn run_limits(mut self, limits: RunLimits) -> Self {
+        self.run_limits = limits;
+        self
+    }
+
+    /// The program as given, without the patches.
+    pub fn program(&self) -> &[C] {
+        &self.program
+    }
+
+    /// In the order they were made; later patches to a cell win.
+    pub fn patches(&self) -> &[(usize, C)] {
+        &self.patches
+    }
+
+    /// Everything queued so far, in order.
+    pub fn queued_inputs(&self) -> &[C] {
+        &self.inputs
+    }
+
+    // Whether the run could stop for anything other than the program itself.
+    pub(super) fn is_limited(&self) -> bool {
+        self.memory_limits != MemoryLimits::default() || !self.run_limits.is_unlimited()
+    }
+
+    /// A machine at pc 0, ready to run.
+    pub fn build(self) -> IntCode<C> {
+        let mut intcode = IntCode::new(self.program);
+        for (address, value) in self.patches {
+            intcode.memory[address] = value;
+        }
+        intcode.inputs.extend(self.inputs);
+        if let Some(engine) = self.engine {
+            intcode.set_engine(engine);
+        }
+        intcode.memory.limits = self.memory_limits;
+        intcode.limits = self.run_limits;
+        intcode
+    }
+}
+
+/// Parses a comma separated program.
+impl<C: Cell> std::str::FromStr for IntCodeBuilder<C> {
+    type Err = C::Err;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self::new(
+            s.split(',').map(|l| l.parse()).collect::<Result<_, _>>()?,
+        ))
+    }
+}
+
+impl IntCode {
+    /// Machines with other cell types start from `IntCodeBuilder::new`.
+    pub fn builder(program: Vec<IntCodeCell>) -> IntCodeBuilder {
+        IntCodeBuilder::new(program)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build() {
+        let mut intcode = "3,0,4,0,99"
+            .parse::<IntCodeBuilder>()
+            .unwrap()
+            .patch(5000, 7)
+            .input(3)
+            .inputs(vec![4, 5])
+            .engine(Engine::Compiled)
+            .run_limits(RunLimits {
+                instruction_budget: Some(10),
+                ..RunLimits::default()
+            })
+            .build();
+
+        assert_eq!(intcode.cell(5000), 7);
+        assert_eq!(intcode.engine(), Engine::Compiled);
+        assert_eq!(intcode.pending_inputs().collect::<Vec<_>>(), vec![3, 4, 5]);
+        assert_eq!(intcode.resume(), Ok(StepResult::Output(3)));
+        assert_eq!(intcode.run_limits().instruction_budget, Some(8));
+    }
+}
@@ -0,0 +1,187 @@
+//! The value types a machine can compute with.
+
+use num::{BigInt, ToPrimitive, Zero};
+use std::fmt;
+use std::str::FromStr;
+
+/// Anything a machine can keep in memory. Fixed-width cells report overflow
+/// instead of wrapping.
+pub trait Cell: Clone + Default + Ord + fmt::Debug + fmt::Display + FromStr {
+    /// Converts a plain value, such as an opcode or a boolean result.
+    fn from_i64(value: i64) -> Self;
+    /// `None` if the value doesn't fit.
+    fn to_i64(&self) -> Option<i64>;
+    /// `None` on overflow.
+    fn checked_add(&self, other: &Self) -> Option<Self>;
+    /// `None` on overflow.
+    fn checked_mul(&self, other: &Self) -> Option<Self>;
+
+    /// Whether this is the default value, which is also what unwritten cells hold.
+    fn is_zero(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Errors always report plain i64s, so anything wider is clamped.
+    fn saturating_i64(&self) -> i64 {
+        self.to_i64().unwrap_or(if *self < Self::default() {
+            i64::MIN
+        } else {
+            i64::MAX
+        })
+    }
+}
+
+impl Cell for i64 {
+    #[inline]
+    fn from_i64(value: i64) -> Self {
+        value
+    }
+
+    #[inline]
+    fn to_i64(&self) -> Option<i64> {
+        Some(*self)
+    }
+
+    #[inline]
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        i64::checked_add(*self, *other)
+    }
+
+    #[inline]
+    fn checked_mul(&self, other: &Self) -> Option<Self> {
+        i64::checked_mul(*self, *other)
+    }
+
+    #[inline]
+    fn is_zero(&self) -> bool {
+        *self == 0
+    }
+}
+
+impl Cell for i128 {
+    fn from_i64(value: i64) -> Self {
+        value.into()
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        ToPrimitive::to_i64(self)
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        i128::checked_add(*self, *other)
+    }
+
+    fn checked_mul(&self, other: &Self) -> Option<Self> {
+        i128::checked_mul(*self, *other)
+    }
+}
+
+impl Cell for BigInt {
+    fn from_i64(value: i64) -> Self {
+        value.into()
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        ToPrimitive::to_i64(self)
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        Some(self + other)
+    }
+
+    fn checked_mul(&self, other: &Self) -> Option<Self> {
+        Some(self * other)
+    }
+
+    fn is_zero(&self) -> bool {
+        Zero::is_zero(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::*;
+    use super::*;
+
+    // Squares 2^40 and prints the result.
+    const SQUARE: &str = "1102,1099511627776,1099511627776,7,4,7,99,0";
+
+    #[test]
+    fn overflow() {
+        assert_eq!(
+            SQUARE.parse::<IntCode>().unwrap().run_with_input(&[]),
+            Err(IntCodeError::Overflow {
+                pc: 0,
+                instruction: 1102
+            })
+        );
+        assert_eq!(
+            SQUARE.parse::<IntCode<i128>>().unwrap().run_with_input(&[]),
+            Ok(vec![1 << 80])
+        );
+        assert_eq!(
+            "1102,1,1,-1,99"
+                .parse::<IntCode<i128>>()
+                .unwrap()
+                .run_with_input(&[]),
+            Err(IntCodeError::NegativeAddress {
+                pc: 0,
+                instruction: 1102,
+                address: -1
+            })
+        );
+
+        // Addresses too big for an i64 saturate in the error.
+        let mut intcode = "1102,1,1,0,99".parse::<IntCode<i128>>().unwrap();
+        intcode.replace_cell(3, 1 << 80);
+        assert_eq!(
+            intcode.run_with_input(&[]),
+            Err(IntCodeError::AddressOutOfRange {
+                pc: 0,
+                instruction: 1102,
+                address: i64::MAX
+            })
+        );
+    }
+
+    #[test]
+    fn bignum() {
+        let mut intcode = SQUARE.parse::<IntCode<BigInt>>().unwrap();
+        intcode.replace_cell(1, "1000000000000000000000".parse().unwrap());
+        intcode.replace_cell(2, "1000000000000000000000".parse().unwrap());
+        assert_eq!(
+            intcode.run_with_input(&[]).unwrap(),
+            vec!["1000000000000000000000000000000000000000000"
+                .parse()
+                .unwrap()]
+        );
+    }
+
+    #[test]
+    fn day9() {
+        let program = include_str!("../../input/2019/day9.txt").trim();
+        let expected = program
+            .parse::<IntCode>()
+            .unwrap()
+            .run_with_input(&[1])
+            .unwrap();
+
+        let wide = program
+            .parse::<IntCode<i128>>()
+            .unwrap()
+            .run_with_input(&[1])
+            .unwrap();
+        assert_eq!(
+            wide,
+            expected.iter().map(|&x| x.into()).collect::<Vec<i128>>()
+        );
+
+        let mut big = program.parse::<IntCode<BigInt>>().unwrap();
+        big.set_engine(Engine::Compiled);
+        let big = big.run_with_input(&[1.into()]).unwrap();
+        assert_eq!(
+            big,
+            expected.iter().map(|&x| x.into()).collect::<Vec<BigInt>>()
+        );
+    }
+}
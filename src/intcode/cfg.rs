@@ -0,0 +1,426 @@
+//! Control flow graphs of basic blocks, recovered from a program.
+
+use super::*;
+use petgraph::graph::{DiGraph, NodeIndex};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+/// How a basic block ends.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Terminator {
+    /// Runs on into the next block, which something else jumps to. A jump that can
+    /// never be taken ends a block this way too.
+    Fallthrough,
+    /// A jump that is always taken.
+    Jump,
+    /// A jump that depends on a value only known at run time.
+    Branch,
+    /// Jumps through a position or relative operand, so its targets are guessed.
+    /// Every return site, meaning a constant the program stores that points just
+    /// past a direct unconditional jump, is treated as a possible target. Without
+    /// any return sites, every block is. Jump tables and computed targets aren't
+    /// found, so edges out of these blocks can be missing.
+    Indirect,
+    /// Halts.
+    Halt,
+    /// Runs into something that doesn't decode, or off the end of the program.
+    Invalid,
+}
+
+/// Why control can pass from one block to another.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Edge {
+    /// The first block runs on into the second.
+    Fallthrough,
+    /// A jump to the second block, when it's taken.
+    Taken,
+    /// A conditional jump not taken, falling through to the second block.
+    NotTaken,
+    /// An indirect jump that might go to the second block.
+    Indirect,
+}
+
+/// A run of instructions only ever entered at the start.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BasicBlock {
+    /// The address of the first instruction.
+    pub start: usize,
+    /// The address just past the last instruction.
+    pub end: usize,
+    /// How many instructions there are.
+    pub instructions: usize,
+    /// How the block ends.
+    pub terminator: Terminator,
+    listing: String,
+}
+
+/// The blocks reachable from address 0 and the edges between them.
+pub struct ControlFlowGraph {
+    /// The graph itself, for use with petgraph's algorithms.
+    pub graph: DiGraph<BasicBlock, Edge>,
+    blocks: BTreeMap<usize, NodeIndex>,
+}
+
+enum Target {
+    Direct(usize),
+    Indirect,
+    Invalid,
+}
+
+impl IntCode {
+    /// Recovers the control flow graph of the program as it is now, from address 0.
+    pub fn control_flow_graph(&self) -> ControlFlowGraph {
+        let program_len = self.memory.starting_memory.len();
+        let decode = |address: usize| match Instruction::new(address, self.memory[address]) {
+            Ok(instr) if instr.is_canonical() && address + instr.len() <= program_len => {
+                Some(instr)
+            }
+            _ => None,
+        };
+        let operand = |instr: Instruction, index: usize| {
+            (instr.modes[index], self.memory[instr.pc + index + 1])
+        };
+        let target = |instr: Instruction| match operand(instr, 1) {
+            (Immediate, target) if target >= 0 && decode(target as usize).is_some() => {
+                Target::Direct(target as usize)
+            }
+            (Immediate, _) => Target::Invalid,
+            _ => Target::Indirect,
+        };
+        // Whether a jump with a constant condition always or never goes.
+        let constant = |instr: Instruction| match operand(instr, 0) {
+            (Immediate, cond) => Some((cond != 0) == (instr.opcode == JumpIfTrue)),
+            _ => None,
+        };
+
+        // Find every reachable instruction, adding return sites as extra roots
+        // whenever an indirect jump could reach them.
+        let mut reachable = BTreeSet::new();
+        let mut leaders = BTreeSet::new();
+        let mut stored_constants = BTreeSet::new();
+        let mut after_calls = BTreeSet::new();
+        let mut return_sites = BTreeSet::new();
+        let mut has_indirect = false;
+        let mut work = vec![0];
+        leaders.insert(0);
+
+        loop {
+            while let Some(address) = work.pop() {
+                if reachable.contains(&address) {
+                    continue;
+                }
+                let instr = match decode(address) {
+                    Some(instr) => instr,
+                    None => continue,
+                };
+                reachable.insert(address);
+                let next = address + instr.len();
+
+                match instr.opcode {
+                    JumpIfTrue | JumpIfFalse => {
+                        let jumps = constant(instr);
+                        if jumps == Some(true) {
+                            // Only direct jumps can be calls that something returns from.
+                            if let Target::Direct(_) = target(instr) {
+                                after_calls.insert(next);
+                            }
+                        } else {
+                            leaders.insert(next);
+                            work.push(next);
+                        }
+                        if jumps != Some(false) {
+                            match target(instr) {
+                                Target::Direct(target) => {
+                                    leaders.insert(target);
+                                    work.push(target);
+                                }
+                                Target::Indirect => has_indirect = true,
+                                Target::Invalid => (),
+                            }
+                        }
+                    }
+                    Terminate => (),
+                    _ => {
+                        let stored = match (instr.opcode, operand(instr, 0), operand(instr, 1)) {
+                            (Add, (Immediate, a), (Immediate, b)) => a.checked_add(b),
+                            (Multiply, (Immediate, a), (Immediate, b)) => a.checked_mul(b),
+                            _ => None,
+                        };
+                        if let Some(value) = stored.filter(|&value| value >= 0) {
+                            stored_constants.insert(value as usize);
+                        }
+                        work.push(next);
+                    }
+                }
+            }
+
+            if !has_indirect {
+                break;
+            }
+            return_sites = stored_constants
+                .intersection(&after_calls)
+                .copied()
+                .collect::<BTreeSet<_>>();
+            work.extend(return_sites.difference(&reachable));
+            if work.is_empty() {
+                break;
+            }
+            leaders.extend(&work);
+        }
+
+        let mut graph = DiGraph::new();
+        let mut blocks = BTreeMap::new();
+        let mut successors = Vec::new();
+
+        for &start in leaders.iter().filter(|&&start| reachable.contains(&start)) {
+            let (mut address, mut instructions) = (start, 0);
+            let mut exits = Vec::new();
+
+            let terminator = loop {
+                let instr = match decode(address) {
+                    Some(instr) => instr,
+                    None => break Terminator::Invalid,
+                };
+                instructions += 1;
+                let next = address + instr.len();
+
+                match instr.opcode {
+                    JumpIfTrue | JumpIfFalse => {
+                        let jumps = constant(instr);
+                        if jumps != Some(true) {
+                            exits.push((Some(next), Edge::NotTaken));
+                        }
+                        if jumps != Some(false) {
+                            match target(instr) {
+                                Target::Direct(target) => exits.push((Some(target), Edge::Taken)),
+                                Target::Indirect => exits.push((None, Edge::Indirect)),
+                                Target::Invalid => (),
+                            }
+                        }
+                        address = next;
+                        break match (jumps, target(instr)) {
+                            (Some(false), _) => Terminator::Fallthrough,
+                            (_, Target::Indirect) => Terminator::Indirect,
+                            (Some(true), _) => Terminator::Jump,
+                            (None, _) => Terminator::Branch,
+                        };
+                    }
+                    Terminate => {
+                        address = next;
+                        break Terminator::Halt;
+                    }
+                    _ => {
+                        address = next;
+                        if leaders.contains(&address) {
+                            exits.push((Some(address), Edge::Fallthrough));
+                            break Terminator::Fallthrough;
+                        }
+                    }
+                }
+            };
+
+            let listing = self.disassemble_range(start, address - start).to_string();
+            let node = graph.add_node(BasicBlock {
+                start,
+                end: address,
+                instructions,
+                terminator,
+                listing,
+            });
+            blocks.insert(start, node);
+            successors.push((node, exits));
+        }
+
+        for (node, exits) in successors {
+            for (target, edge) in exits {
+                match target {
+                    Some(target) => {
+                        if let Some(&to) = blocks.get(&target) {
+                            graph.add_edge(node, to, edge);
+                        }
+                    }
+                    // Nothing that looks like a return site, so it could go anywhere.
+                    None if return_sites.is_empty() => {
+                        for &to in blocks.values() {
+                            graph.add_edge(node, to, edge);
+                        }
+                    }
+                    None => {
+                        for target in &return_sites {
+                            if let Some(&to) = blocks.get(target) {
+                                graph.add_edge(node, to, edge);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        ControlFlowGraph { graph, blocks }
+    }
+}
+
+impl ControlFlowGraph {
+    /// The block starting at `start`, if there is one.
+    pub fn block_at(&self, start: usize) -> Option<&BasicBlock> {
+        self.blocks.get(&start).map(|&node| &self.graph[node])
+    }
+
+    /// Every block, in address order.
+    pub fn blocks(&self) -> impl Iterator<Item = &BasicBlock> + '_ {
+        self.blocks.values().map(move |&node| &self.graph[node])
+    }
+
+    /// The blocks control can go to from the one at `start`, in address order, or
+    /// `None` if no block starts there.
+    pub fn successors(&self, start: usize) -> Option<Vec<(usize, Edge)>> {
+        let &node = self.blocks.get(&start)?;
+        let mut successors = self
+            .graph
+            .edges(node)
+            .map(|edge| {
+                use petgraph::visit::EdgeRef;
+                (self.graph[edge.target()].start, *edge.weight())
+            })
+            .collect::<Vec<_>>();
+        successors.sort_by_key(|&(start, _)| start);
+        Some(successors)
+    }
+
+    /// The graph in Graphviz format, with each block's disassembly as its label.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::new();
+        writeln!(dot, "digraph cfg {{").unwrap();
+        writeln!(dot, "    node [shape=box fontname=monospace];").unwrap();
+
+        for block in self.blocks() {
+            let label = block
+                .listing
+                .lines()
+                .map(|line| format!("{}\\l", line.replace('\\', "\\\\").replace('"', "\\\"")))
+                .collect::<String>();
+            writeln!(dot, "    b{} [label=\"{}\"];", block.start, label).unwrap();
+        }
+
+        for &from in self.blocks.keys() {
+            for (to, edge) in self.successors(from).unwrap_or_default() {
+                let style = match edge {
+                    Edge::Fallthrough => "",
+                    Edge::Taken => " [label=\"T\"]",
+                    Edge::NotTaken => " [label=\"F\"]",
+                    Edge::Indirect => " [style=dashed]",
+                };
+                writeln!(dot, "    b{} -> b{}{};", from, to, style).unwrap();
+            }
+        }
+
+        writeln!(dot, "}}").unwrap();
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn subroutine() {
+        let intcode = IntCode::assemble(
+            "
+                arb   #100
+                in    [value]
+                add   #back, #0, rb+0
+                jt    #1, #double
+            back:
+                out   [value]
+                hlt
+            double:
+                mul   [value], #2, [value]
+                lt    [value], #10, [small]
+                jf    [small], #done
+                add   [value], #1, [value]
+            done:
+                jt    #1, rb+0
+            value: .data 0
+            small: .data 0
+            ",
+        )
+        .unwrap();
+        let cfg = intcode.control_flow_graph();
+
+        let blocks = cfg
+            .blocks()
+            .map(|block| (block.start, block.end, block.terminator))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            blocks,
+            vec![
+                (0, 11, Terminator::Jump),
+                (11, 14, Terminator::Halt),
+                (14, 25, Terminator::Branch),
+                (25, 29, Terminator::Fallthrough),
+                (29, 32, Terminator::Indirect),
+            ]
+        );
+        assert_eq!(cfg.successors(0), Some(vec![(14, Edge::Taken)]));
+        assert_eq!(
+            cfg.successors(14),
+            Some(vec![(25, Edge::NotTaken), (29, Edge::Taken)])
+        );
+        assert_eq!(cfg.successors(25), Some(vec![(29, Edge::Fallthrough)]));
+        assert_eq!(cfg.successors(29), Some(vec![(11, Edge::Indirect)]));
+        assert_eq!(cfg.successors(12), None);
+        assert_eq!(cfg.block_at(11).unwrap().instructions, 2);
+
+        let dot = cfg.to_dot();
+        assert!(dot.starts_with("digraph cfg {\n"));
+        assert!(dot.contains("    b11 [label=\"    11: out   [32]\\l    13: hlt\\l\"];\n"));
+        assert!(dot.contains("    b29 -> b11 [style=dashed];\n"));
+    }
+
+    #[test]
+    fn unknown_targets() {
+        // Jumps to an address it reads, with nothing that looks like a return site.
+        let intcode = IntCode::assemble(
+            "
+                in    [target]
+                jf    [target], #done
+                jt    #1, [target]
+                out   #1
+            done:
+                hlt
+            target: .data 0
+            ",
+        )
+        .unwrap();
+        let cfg = intcode.control_flow_graph();
+
+        assert_eq!(cfg.block_at(5).unwrap().terminator, Terminator::Indirect);
+        assert_eq!(
+            cfg.successors(5),
+            Some(vec![
+                (0, Edge::Indirect),
+                (5, Edge::Indirect),
+                (10, Edge::Indirect)
+            ])
+        );
+    }
+
+    #[test]
+    fn puzzle_programs() {
+        for program in &[
+            include_str!("../../input/2019/day13.txt"),
+            include_str!("../../input/2019/day17.txt"),
+        ] {
+            let cfg = program
+                .trim()
+                .parse::<IntCode>()
+                .unwrap()
+                .control_flow_graph();
+            assert!(cfg.graph.node_count() > 10);
+            assert!(cfg
+                .blocks()
+                .all(|block| block.terminator != Terminator::Invalid));
+        }
+    }
+}
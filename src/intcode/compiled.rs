@@ -0,0 +1,347 @@
+//! Choosing between the plain interpreter and the engine that decodes instructions ahead.
+
+use super::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static COMPILED_BY_DEFAULT: AtomicBool = AtomicBool::new(false);
+
+/// How a machine executes its instructions. Both behave the same, faults included.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Engine {
+    /// Decodes every instruction as it's run.
+    Interpreter,
+    /// Decodes each instruction once and keeps it until something writes over it.
+    Compiled,
+}
+
+impl Engine {
+    /// Picks the engine for every machine created from now on. Machines that already
+    /// exist keep theirs.
+    pub fn set_default(engine: Self) {
+        COMPILED_BY_DEFAULT.store(engine == Engine::Compiled, Ordering::Relaxed);
+    }
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        if COMPILED_BY_DEFAULT.load(Ordering::Relaxed) {
+            Engine::Compiled
+        } else {
+            Engine::Interpreter
+        }
+    }
+}
+
+impl std::str::FromStr for Engine {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "interpreter" => Ok(Engine::Interpreter),
+            "compiled" => Ok(Engine::Compiled),
+            _ => Err(format!("unknown engine {:?}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Engine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Engine::Interpreter => write!(f, "interpreter"),
+            Engine::Compiled => write!(f, "compiled"),
+        }
+    }
+}
+
+#[derive(Clone)]
+enum Operand<C> {
+    Immediate(C),
+    Position(usize),
+    Relative(C),
+}
+
+#[derive(Clone)]
+enum Slot<C> {
+    Undecoded,
+    // Anything the fast path can't express is left to the interpreter,
+    // which also produces the proper error.
+    Interpret,
+    Op(Opcode, [Operand<C>; 3]),
+}
+
+#[derive(Clone)]
+pub(super) struct Code<C> {
+    pub(super) engine: Engine,
+    slots: Vec<Slot<C>>,
+}
+
+// Raised by the fast path to hand the current instruction back to the interpreter.
+struct Fallback;
+
+impl<C> Code<C> {
+    pub(super) fn new(engine: Engine) -> Self {
+        Self {
+            engine,
+            slots: Vec::new(),
+        }
+    }
+
+    #[inline]
+    pub(super) fn invalidate(&mut self, address: usize) {
+        // Instructions are at most 4 cells long, so only those starting up to 3 cells
+        // before the write can cover it.
+        let end = std::cmp::min(address + 1, self.slots.len());
+        let start = address.saturating_sub(Add.parameters());
+        if start < end {
+            for slot in &mut self.slots[start..end] {
+                *slot = Slot::Undecoded;
+            }
+        }
+    }
+}
+
+fn decode<C: Cell>(memory: &Memory<C>, pc: usize) -> Slot<C> {
+    let instr = match Instruction::decode(pc, &memory[pc]) {
+        Ok(instr) => instr,
+        Err(_) => return Slot::Interpret,
+    };
+
+    // Only the starting program is tracked for writes.
+    if pc + instr.len() > memory.starting_memory.len() {
+        return Slot::Interpret;
+    }
+
+    let mut operands = [
+        Operand::Position(0),
+        Operand::Position(0),
+        Operand::Position(0),
+    ];
+    for (index, operand) in operands
+        .iter_mut()
+        .enumerate()
+        .take(instr.opcode.parameters())
+    {
+        let raw = &memory[pc + index + 1];
+        *operand = match instr.modes[index] {
+            Position => match raw.to_i64() {
+                Some(address) if address >= 0 => Operand::Position(address as usize),
+                _ => return Slot::Interpret,
+            },
+            Immediate => Operand::Immediate(raw.clone()),
+            Relative => Operand::Relative(raw.clone()),
+        };
+    }
+
+    let writes_immediate = match instr.opcode {
+        Add | Multiply | LessThan | Equals => instr.modes[2] == Immediate,
+        Input => instr.modes[0] == Immediate,
+        _ => false,
+    };
+    if writes_immediate {
+        return Slot::Interpret;
+    }
+
+    Slot::Op(instr.opcode, operands)
+}
+
+impl<C: Cell> IntCode<C> {
+    /// The engine the machine runs on.
+    pub fn engine(&self) -> Engine {
+        self.code.engine
+    }
+
+    /// Switches engines; the machine's state carries over.
+    pub fn set_engine(&mut self, engine: Engine) {
+        self.code = Code::new(engine);
+    }
+
+    #[inline]
+    pub(super) fn step_compiled(&mut self) -> Result<Option<StepResult<C>>, IntCodeError> {
+        if self.code.slots.is_empty() {
+            self.code.slots = vec![Slot::Undecoded; self.memory.starting_memory.len()];
+        }
+
+        let slot = match self.code.slots.get(self.pc) {
+            Some(Slot::Undecoded) => {
+                let slot = decode(&self.memory, self.pc);
+                self.code.slots[self.pc] = slot.clone();
+                slot
+            }
+            Some(slot) => slot.clone(),
+            None => Slot::Interpret,
+        };
+
+        match slot {
+            Slot::Op(opcode, operands) => match self.execute(opcode, operands) {
+                Ok(result) => Ok(result),
+                Err(Fallback) => self.interpret(&mut ()),
+            },
+            _ => self.interpret(&mut ()),
+        }
+    }
+
+    // Nothing is modified before the last check that can fall back, so the interpreter
+    // can always redo the instruction from scratch.
+    #[inline]
+    fn execute(
+        &mut self,
+        opcode: Opcode,
+        [a, b, c]: [Operand<C>; 3],
+    ) -> Result<Option<StepResult<C>>, Fallback> {
+        Ok(match opcode {
+            Add | Multiply | LessThan | Equals => {
+                let (value1, value2) = (self.load(a)?, self.load(b)?);
+                let result = match opcode {
+                    Add => value1.checked_add(&value2).ok_or(Fallback)?,
+                    Multiply => value1.checked_mul(&value2).ok_or(Fallback)?,
+                    LessThan => C::from_i64((value1 < value2).into()),
+                    Equals => C::from_i64((value1 == value2).into()),
+                    _ => unreachable!(),
+                };
+                self.store(c, result)?;
+                self.pc += 4;
+                None
+            }
+            JumpIfTrue | JumpIfFalse => {
+                // The target is read even when the jump isn't taken, like the interpreter does.
+                let (cond, target) = (self.load(a)?, self.load(b)?);
+                if cond.is_zero() != (opcode == JumpIfTrue) {
+                    self.pc = cell_address(&target)?;
+                } else {
+                    self.pc += 3;
+                }
+                None
+            }
+            Input => match self.inputs.front() {
+                Some(value) => {
+                    self.store(a, value.clone())?;
+                    self.inputs.pop_front();
+                    self.pc += 2;
+                    None
+                }
+                None => Some(StepResult::NeedsInput),
+            },
+            Output => {
+                let value = self.load(a)?;
+                self.pc += 2;
+                Some(StepResult::Output(value))
+            }
+            AdjustRelativeBase => {
+                let offset = self.load(a)?;
+                self.relative_base = self.relative_base.checked_add(&offset).ok_or(Fallback)?;
+                self.pc += 2;
+                None
+            }
+            Terminate => Some(StepResult::Halted),
+        })
+    }
+
+    #[inline]
+    fn load(&self, operand: Operand<C>) -> Result<C, Fallback> {
+        let address = match operand {
+            Operand::Immediate(value) => return Ok(value),
+            Operand::Position(address) => address,
+            Operand::Relative(offset) => self.offset_address(&offset)?,
+        };
+        self.memory.read(address).cloned().map_err(|_| Fallback)
+    }
+
+    #[inline]
+    fn store(&mut self, operand: Operand<C>, value: C) -> Result<(), Fallback> {
+        let address = match operand {
+            Operand::Position(address) => address,
+            Operand::Relative(offset) => self.offset_address(&offset)?,
+            Operand::Immediate(_) => return Err(Fallback),
+        };
+
+        self.memory.write(address, value).map_err(|_| Fallback)?;
+        self.code.invalidate(address);
+        Ok(())
+    }
+
+    #[inline]
+    fn offset_address(&self, offset: &C) -> Result<usize, Fallback> {
+        cell_address(&self.relative_base.checked_add(offset).ok_or(Fallback)?)
+    }
+}
+
+#[inline]
+fn cell_address<C: Cell>(address: &C) -> Result<usize, Fallback> {
+    match address.to_i64() {
+        Some(address) if address >= 0 => Ok(address as usize),
+        _ => Err(Fallback),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outputs(mut intcode: IntCode, engine: Engine, inputs: &[IntCodeCell]) -> Vec<IntCodeCell> {
+        intcode.set_engine(engine);
+        intcode.run_with_input(inputs).unwrap()
+    }
+
+    #[test]
+    fn self_modifying() {
+        // Overwrites the immediate operand and then the opcode of the `out` it loops back to.
+        let intcode = IntCode::assemble(
+            "
+            again:
+                out   #1
+                add   [again+1], #1, [again+1]
+                lt    [again+1], #4, [more]
+                jt    [more], #again
+                add   #4, #0, [again+1]
+                eq    [again+1], #4, [more]
+                mul   [more], #99, [again]
+                jt    #1, #again
+            more: .data 0
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(
+            outputs(intcode.clone(), Engine::Interpreter, &[]),
+            vec![1, 2, 3]
+        );
+        assert_eq!(outputs(intcode, Engine::Compiled, &[]), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn matches_interpreter() {
+        for &(program, inputs) in &[
+            (include_str!("../../input/2019/day5.txt"), &[5][..]),
+            (include_str!("../../input/2019/day9.txt"), &[2][..]),
+        ] {
+            let intcode = program.trim().parse::<IntCode>().unwrap();
+            assert_eq!(
+                outputs(intcode.clone(), Engine::Compiled, inputs),
+                outputs(intcode, Engine::Interpreter, inputs)
+            );
+        }
+    }
+
+    #[test]
+    fn errors() {
+        for &program in &[
+            "1,0,0,0,42",
+            "1101,1,1,-1,99",
+            "9,5,204,-7,99,0",
+            "1105,1,-1",
+            "1,0,0,1,104,2,99",
+            "2105,0,-1000,99",
+        ] {
+            let intcode = program.parse::<IntCode>().unwrap();
+            let mut compiled = intcode.clone();
+            compiled.set_engine(Engine::Compiled);
+            assert_eq!(
+                compiled.run_with_input(&[]),
+                intcode.run_with_input(&[]),
+                "{}",
+                program
+            );
+        }
+    }
+}
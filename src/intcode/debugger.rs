@@ -0,0 +1,561 @@
+//! The command interpreter behind the `debugger` binary.
+
+use super::history::History;
+use super::*;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+// Steps `c` or `s` run before handing control back, so a program that never stops
+// can't hang the prompt.
+const CONTINUE_STEPS: u64 = 1_000_000;
+// Most cells `x` and `l` will show at once.
+const MAX_CELLS_SHOWN: usize = 1000;
+
+const HELP: &str = "\
+s [n]          step n instructions (default 1), pausing every million steps
+c              continue until a breakpoint, watchpoint, input request or halt,
+               pausing every million steps
+bs [n]         step back n instructions (default 1)
+bc             continue backwards until a breakpoint or watchpoint
+lw <addr>      show when a memory cell was last written
+b [addr]       add a breakpoint on pc, or list breakpoints
+db <addr>      delete a breakpoint
+w [addr]       watch a memory cell for changes, or list watchpoints
+dw <addr>      delete a watchpoint
+x <addr> [n]   show n memory cells (default 1)
+set <addr> <v> write v into a memory cell
+pc [v]         show or set the pc
+rb [v]         show or set the relative base
+in <v>...      queue input values
+out            show and clear pending output
+l [addr] [n]   disassemble n cells starting at addr (default pc, 20)
+r              show registers and queued input
+q              quit";
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Stop {
+    Stepped,
+    Breakpoint(usize),
+    Watchpoint {
+        address: usize,
+        old: IntCodeCell,
+        new: IntCodeCell,
+    },
+    NeedsInput,
+    Halted,
+    // Stepping backwards reached the point the debugger started from.
+    Start,
+    // Continuing ran for the whole continue limit without otherwise stopping.
+    Paused,
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Stop::Stepped => write!(f, "stepped"),
+            Stop::Breakpoint(pc) => write!(f, "breakpoint at {}", pc),
+            Stop::Watchpoint { address, old, new } => {
+                write!(f, "watchpoint [{}]: {} -> {}", address, old, new)
+            }
+            Stop::NeedsInput => write!(f, "waiting for input"),
+            Stop::Halted => write!(f, "halted"),
+            Stop::Start => write!(f, "at start of history"),
+            Stop::Paused => write!(f, "paused, still running"),
+        }
+    }
+}
+
+pub struct Debugger {
+    intcode: IntCode,
+    breakpoints: BTreeSet<usize>,
+    watchpoints: BTreeMap<usize, IntCodeCell>,
+    // Each output with the step count just after it, to drop it on stepping back.
+    outputs: Vec<(u64, IntCodeCell)>,
+    history: History,
+    continue_limit: u64,
+    // Set when the machine may have been changed outside of stepping.
+    dirty: bool,
+}
+
+impl Debugger {
+    pub fn new(intcode: IntCode) -> Self {
+        Self {
+            history: History::new(&intcode),
+            continue_limit: CONTINUE_STEPS,
+            intcode,
+            breakpoints: BTreeSet::new(),
+            watchpoints: BTreeMap::new(),
+            outputs: Vec::new(),
+            dirty: false,
+        }
+    }
+
+    pub fn intcode(&self) -> &IntCode {
+        &self.intcode
+    }
+
+    /// Changes made through this are checkpointed the next time the debugger steps,
+    /// so stepping back past them restores the old state.
+    pub fn intcode_mut(&mut self) -> &mut IntCode {
+        self.dirty = true;
+        &mut self.intcode
+    }
+
+    /// Steps `resume`, or `s` with a larger count, takes before stopping with
+    /// `Stop::Paused`.
+    pub fn set_continue_limit(&mut self, steps: u64) {
+        self.continue_limit = steps;
+    }
+
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    pub fn add_breakpoint(&mut self, pc: usize) {
+        self.breakpoints.insert(pc);
+    }
+
+    pub fn remove_breakpoint(&mut self, pc: usize) -> bool {
+        self.breakpoints.remove(&pc)
+    }
+
+    pub fn add_watchpoint(&mut self, address: usize) {
+        self.watchpoints.insert(address, self.intcode.cell(address));
+    }
+
+    pub fn remove_watchpoint(&mut self, address: usize) -> bool {
+        self.watchpoints.remove(&address).is_some()
+    }
+
+    pub fn take_outputs(&mut self) -> Vec<IntCodeCell> {
+        std::mem::take(&mut self.outputs)
+            .into_iter()
+            .map(|(_, value)| value)
+            .collect()
+    }
+
+    pub fn step(&mut self) -> Result<Stop, IntCodeError> {
+        self.sync_history();
+        let result = self.history.step(&mut self.intcode)?;
+
+        if let Some(stop) = self.check_watchpoints() {
+            return Ok(stop);
+        }
+
+        Ok(match result {
+            None => Stop::Stepped,
+            Some(StepResult::Output(value)) => {
+                self.outputs.push((self.history.steps(), value));
+                Stop::Stepped
+            }
+            Some(StepResult::NeedsInput) => Stop::NeedsInput,
+            Some(StepResult::Halted) => Stop::Halted,
+            Some(StepResult::Interrupted(_)) => unreachable!(),
+        })
+    }
+
+    pub fn resume(&mut self) -> Result<Stop, IntCodeError> {
+        for _ in 0..self.continue_limit {
+            match self.step()? {
+                Stop::Stepped if self.breakpoints.contains(&self.intcode.pc) => {
+                    return Ok(Stop::Breakpoint(self.intcode.pc))
+                }
+                Stop::Stepped => (),
+                stop => return Ok(stop),
+            }
+        }
+        Ok(Stop::Paused)
+    }
+
+    pub fn step_back(&mut self) -> Stop {
+        self.sync_history();
+        if !self.history.step_back(&mut self.intcode) {
+            return Stop::Start;
+        }
+
+        let steps = self.history.steps();
+        while self.outputs.last().is_some_and(|&(step, _)| step > steps) {
+            self.outputs.pop();
+        }
+        self.check_watchpoints().unwrap_or(Stop::Stepped)
+    }
+
+    pub fn resume_back(&mut self) -> Stop {
+        loop {
+            match self.step_back() {
+                Stop::Stepped if self.breakpoints.contains(&self.intcode.pc) => {
+                    return Stop::Breakpoint(self.intcode.pc)
+                }
+                Stop::Stepped => (),
+                stop => return stop,
+            }
+        }
+    }
+
+    fn sync_history(&mut self) {
+        if self.dirty {
+            self.history.checkpoint(&self.intcode);
+            self.dirty = false;
+        }
+    }
+
+    fn check_watchpoints(&mut self) -> Option<Stop> {
+        for (&address, old) in &mut self.watchpoints {
+            let new = self.intcode.cell(address);
+            if new != *old {
+                let stop = Stop::Watchpoint {
+                    address,
+                    old: *old,
+                    new,
+                };
+                *old = new;
+                return Some(stop);
+            }
+        }
+        None
+    }
+
+    pub fn execute(&mut self, command: &str) -> Result<String, String> {
+        let mut words = command.split_whitespace();
+        let name = words.next().unwrap_or("");
+        let args = words
+            .map(|word| word.parse::<IntCodeCell>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| e.to_string())?;
+        let address = |index: usize| match args.get(index) {
+            Some(&value) if value >= 0 => Ok(value as usize),
+            Some(value) => Err(format!("bad address {}", value)),
+            None => Err("missing argument, try `help`".to_string()),
+        };
+        let cell_count = |index: usize, default: usize| match args.get(index) {
+            Some(&value) if value >= 0 && value as usize <= MAX_CELLS_SHOWN => Ok(value as usize),
+            Some(_) => Err(format!("can show at most {} cells", MAX_CELLS_SHOWN)),
+            None => Ok(default),
+        };
+
+        let mut output = String::new();
+        match name {
+            "s" | "step" => {
+                let count = args.first().map_or(Ok(1), |_| address(0))? as u64;
+                let mut stop = Stop::Stepped;
+                for _ in 0..count.min(self.continue_limit) {
+                    stop = self.step().map_err(|e| e.to_string())?;
+                    if stop != Stop::Stepped {
+                        break;
+                    }
+                }
+                if stop == Stop::Stepped && count > self.continue_limit {
+                    stop = Stop::Paused;
+                }
+                self.describe_stop(&mut output, stop);
+            }
+            "c" | "continue" => {
+                let stop = self.resume().map_err(|e| e.to_string())?;
+                self.describe_stop(&mut output, stop);
+            }
+            "bs" => {
+                let count = args.first().map_or(Ok(1), |_| address(0))?;
+                let mut stop = Stop::Stepped;
+                for _ in 0..count {
+                    stop = self.step_back();
+                    if stop != Stop::Stepped {
+                        break;
+                    }
+                }
+                self.describe_stop(&mut output, stop);
+            }
+            "bc" => {
+                let stop = self.resume_back();
+                self.describe_stop(&mut output, stop);
+            }
+            "lw" => match self.history.last_write(address(0)?) {
+                Some(write) => writeln!(
+                    output,
+                    "step {} at pc {}, was {}",
+                    write.step, write.pc, write.old
+                )
+                .unwrap(),
+                None => writeln!(output, "not written").unwrap(),
+            },
+            "b" | "break" if args.is_empty() => {
+                for pc in &self.breakpoints {
+                    writeln!(output, "{}", pc).unwrap();
+                }
+            }
+            "b" | "break" => self.add_breakpoint(address(0)?),
+            "db" => {
+                if !self.remove_breakpoint(address(0)?) {
+                    return Err("no such breakpoint".to_string());
+                }
+            }
+            "w" | "watch" if args.is_empty() => {
+                for (address, value) in &self.watchpoints {
+                    writeln!(output, "[{}] = {}", address, value).unwrap();
+                }
+            }
+            "w" | "watch" => self.add_watchpoint(address(0)?),
+            "dw" => {
+                if !self.remove_watchpoint(address(0)?) {
+                    return Err("no such watchpoint".to_string());
+                }
+            }
+            "x" => {
+                let start = address(0)?;
+                let count = cell_count(1, 1)?;
+                for address in start..start + count {
+                    writeln!(output, "[{}] = {}", address, self.intcode.cell(address)).unwrap();
+                }
+            }
+            "set" => {
+                let value = *args.get(1).ok_or("missing value")?;
+                let address = address(0)?;
+                self.intcode_mut().replace_cell(address, value);
+            }
+            "pc" => match args.first() {
+                Some(_) => {
+                    let pc = address(0)?;
+                    self.intcode_mut().set_pc(pc);
+                }
+                None => writeln!(output, "{}", self.intcode.pc).unwrap(),
+            },
+            "rb" => match args.first() {
+                Some(&value) => self.intcode_mut().set_relative_base(value),
+                None => writeln!(output, "{}", self.intcode.relative_base).unwrap(),
+            },
+            "in" => {
+                for &value in &args {
+                    self.intcode_mut().provide_input(value);
+                }
+            }
+            "out" => {
+                for value in self.take_outputs() {
+                    writeln!(output, "{}", value).unwrap();
+                }
+            }
+            "l" | "list" => {
+                let start = args.first().map_or(Ok(self.intcode.pc), |_| address(0))?;
+                let count = cell_count(1, 20)?;
+                write!(output, "{}", self.intcode.disassemble_range(start, count)).unwrap();
+            }
+            "r" | "regs" => {
+                writeln!(
+                    output,
+                    "pc {}  rb {}  input {:?}  pending output {}  step {}",
+                    self.intcode.pc,
+                    self.intcode.relative_base,
+                    self.intcode.inputs,
+                    self.outputs.len(),
+                    self.history.steps()
+                )
+                .unwrap();
+            }
+            "h" | "help" => writeln!(output, "{}", HELP).unwrap(),
+            "" => (),
+            _ => return Err(format!("unknown command {:?}, try `help`", name)),
+        }
+
+        Ok(output)
+    }
+
+    fn describe_stop(&self, output: &mut String, stop: Stop) {
+        if stop != Stop::Stepped {
+            writeln!(output, "{}", stop).unwrap();
+        }
+        let pc = self.intcode.pc;
+        let len = Instruction::new(pc, self.intcode.cell(pc)).map_or(1, |instr| instr.len());
+        write!(output, "{}", self.intcode.disassemble_range(pc, len)).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn debugger() -> Debugger {
+        Debugger::new(
+            IntCode::assemble(
+                "
+                loop:
+                    in    [value]
+                    add   [value], [total], [total]
+                    out   [total]
+                    jt    #1, #loop
+                value: .data 0
+                total: .data 0
+                ",
+            )
+            .unwrap(),
+        )
+    }
+
+    #[test]
+    fn breakpoints_and_watchpoints() {
+        let mut debugger = debugger();
+        assert_eq!(debugger.resume(), Ok(Stop::NeedsInput));
+
+        debugger.intcode_mut().provide_input(5);
+        debugger.add_breakpoint(6);
+        assert_eq!(debugger.resume(), Ok(Stop::Breakpoint(6)));
+        assert_eq!(debugger.intcode().cell(12), 5);
+
+        debugger.add_watchpoint(12);
+        debugger.intcode_mut().provide_input(3);
+        assert_eq!(
+            debugger.resume(),
+            Ok(Stop::Watchpoint {
+                address: 12,
+                old: 5,
+                new: 8
+            })
+        );
+        assert_eq!(debugger.take_outputs(), vec![5]);
+        assert_eq!(debugger.resume(), Ok(Stop::NeedsInput));
+    }
+
+    #[test]
+    fn commands() {
+        let mut debugger = debugger();
+        assert_eq!(debugger.execute("in 2 4").unwrap(), "");
+        assert_eq!(debugger.execute("b 6").unwrap(), "");
+        assert_eq!(
+            debugger.execute("c").unwrap(),
+            "breakpoint at 6\n    6: out   [12]\n"
+        );
+        assert_eq!(debugger.execute("x 11 2").unwrap(), "[11] = 2\n[12] = 2\n");
+        assert_eq!(debugger.execute("set 12 10").unwrap(), "");
+        assert_eq!(debugger.execute("s").unwrap(), "    8: jt    #1, #0\n");
+        assert_eq!(debugger.execute("out").unwrap(), "10\n");
+        assert!(debugger.execute("bogus").is_err());
+        assert!(debugger.execute("x 0 1000").is_ok());
+        assert!(debugger.execute("x 0 1001").is_err());
+        assert!(debugger.execute("l 0 100000000").is_err());
+    }
+
+    #[test]
+    fn time_travel() {
+        let mut debugger = debugger();
+        assert_eq!(debugger.execute("in 2 4 6").unwrap(), "");
+        assert_eq!(debugger.execute("b 8").unwrap(), "");
+        assert_eq!(
+            debugger.execute("c").unwrap(),
+            "breakpoint at 8\n    8: jt    #1, #0\n"
+        );
+        assert_eq!(
+            debugger.execute("c").unwrap(),
+            "breakpoint at 8\n    8: jt    #1, #0\n"
+        );
+        assert_eq!(debugger.execute("x 12").unwrap(), "[12] = 6\n");
+        assert_eq!(
+            debugger.execute("lw 12").unwrap(),
+            "step 5 at pc 2, was 2\n"
+        );
+
+        assert_eq!(
+            debugger.execute("bs 2").unwrap(),
+            "    2: add   [11], [12], [12]\n"
+        );
+        assert_eq!(debugger.execute("x 12").unwrap(), "[12] = 2\n");
+        assert_eq!(
+            debugger.execute("lw 12").unwrap(),
+            "step 1 at pc 2, was 0\n"
+        );
+        assert_eq!(
+            debugger.execute("bc").unwrap(),
+            "breakpoint at 8\n    8: jt    #1, #0\n"
+        );
+        assert_eq!(
+            debugger.execute("bc").unwrap(),
+            "at start of history\n    0: in    [11]\n"
+        );
+        assert_eq!(debugger.execute("lw 12").unwrap(), "not written\n");
+        assert_eq!(debugger.intcode().pending_inputs().count(), 3);
+
+        // Edits are undone along with everything else.
+        assert_eq!(
+            debugger.execute("c").unwrap(),
+            "breakpoint at 8\n    8: jt    #1, #0\n"
+        );
+        assert_eq!(debugger.execute("set 12 100").unwrap(), "");
+        assert_eq!(debugger.execute("s").unwrap(), "    0: in    [11]\n");
+        assert_eq!(debugger.execute("bs 2").unwrap(), "    6: out   [12]\n");
+        assert_eq!(debugger.execute("x 12").unwrap(), "[12] = 2\n");
+    }
+
+    #[test]
+    fn runaway() {
+        let mut debugger = Debugger::new("1105,1,0".parse().unwrap());
+        debugger.set_continue_limit(100);
+        assert_eq!(
+            debugger.execute("c").unwrap(),
+            "paused, still running\nL0:\n    0: jt    #1, #L0\n"
+        );
+        assert_eq!(debugger.history().steps(), 100);
+
+        assert_eq!(
+            debugger.execute("s 9999999999").unwrap(),
+            "paused, still running\nL0:\n    0: jt    #1, #L0\n"
+        );
+        assert_eq!(debugger.history().steps(), 200);
+        assert_eq!(
+            debugger.execute("s 100").unwrap(),
+            "L0:\n    0: jt    #1, #L0\n"
+        );
+    }
+
+    #[test]
+    fn step_back_over_output() {
+        let mut debugger = debugger();
+        assert_eq!(debugger.execute("in 2 4").unwrap(), "");
+        assert_eq!(debugger.execute("s 7").unwrap(), "    8: jt    #1, #0\n");
+        assert_eq!(
+            debugger.execute("r").unwrap(),
+            "pc 8  rb 0  input []  pending output 2  step 7\n"
+        );
+
+        assert_eq!(
+            debugger.execute("bs 2").unwrap(),
+            "    2: add   [11], [12], [12]\n"
+        );
+        assert_eq!(debugger.execute("out").unwrap(), "2\n");
+        assert_eq!(debugger.execute("s 2").unwrap(), "    8: jt    #1, #0\n");
+        assert_eq!(debugger.execute("out").unwrap(), "6\n");
+    }
+
+    #[test]
+    fn late_input() {
+        let mut debugger = Debugger::new(
+            IntCode::assemble(
+                "
+                    in    [counter]
+                loop:
+                    add   [counter], #-1, [counter]
+                    jt    [counter], #loop
+                    in    [counter]
+                    out   [counter]
+                    hlt
+                counter: .data 0
+                ",
+            )
+            .unwrap(),
+        );
+        assert_eq!(debugger.execute("in 3000").unwrap(), "");
+        assert_eq!(
+            debugger.execute("c").unwrap(),
+            "waiting for input\n    9: in    [14]\n"
+        );
+        assert_eq!(debugger.execute("in 7").unwrap(), "");
+        assert_eq!(debugger.execute("c").unwrap(), "halted\n    13: hlt\n");
+        assert_eq!(debugger.execute("out").unwrap(), "7\n");
+
+        assert_eq!(
+            debugger.execute("bc").unwrap(),
+            "at start of history\n    0: in    [14]\n"
+        );
+        assert_eq!(
+            debugger.intcode().pending_inputs().collect::<Vec<_>>(),
+            vec![3000, 7]
+        );
+        assert_eq!(debugger.execute("c").unwrap(), "halted\n    13: hlt\n");
+        assert_eq!(debugger.execute("out").unwrap(), "7\n");
+    }
+}
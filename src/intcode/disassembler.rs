@@ -0,0 +1,240 @@
+//! Turning memory back into assembly.
+
+use super::*;
+use std::collections::{BTreeMap, HashSet};
+
+const DATA_PER_LINE: usize = 8;
+
+/// Assembly for part of memory, printed with `Display`. Anything that doesn't
+/// decode as an instruction is shown as `.data`, and jump targets get labels.
+pub struct Disassembly {
+    lines: Vec<Line>,
+    labels: BTreeMap<usize, String>,
+}
+
+enum Line {
+    Instruction {
+        address: usize,
+        opcode: Opcode,
+        operands: Vec<(Mode, IntCodeCell)>,
+    },
+    Data {
+        address: usize,
+        values: Vec<IntCodeCell>,
+    },
+}
+
+impl Line {
+    fn address(&self) -> usize {
+        match *self {
+            Line::Instruction { address, .. } | Line::Data { address, .. } => address,
+        }
+    }
+}
+
+impl IntCode {
+    /// Disassembles the whole program.
+    pub fn disassemble(&self) -> Disassembly {
+        self.disassemble_range(0, self.memory.starting_memory.len())
+    }
+
+    /// Disassembles `len` cells from `start`. An instruction that would run past the
+    /// end is shown as data.
+    pub fn disassemble_range(&self, start: usize, len: usize) -> Disassembly {
+        let end = start + len;
+        let mut lines = Vec::new();
+        let mut address = start;
+
+        while address < end {
+            match Instruction::new(address, self.memory[address]) {
+                Ok(instr) if instr.is_canonical() && address + instr.len() <= end => {
+                    lines.push(Line::Instruction {
+                        address,
+                        opcode: instr.opcode,
+                        operands: instr.modes[..instr.opcode.parameters()]
+                            .iter()
+                            .zip(address + 1..)
+                            .map(|(&mode, operand)| (mode, self.memory[operand]))
+                            .collect(),
+                    });
+                    address += instr.len();
+                }
+                _ => {
+                    lines.push(Line::Data {
+                        address,
+                        values: vec![self.memory[address]],
+                    });
+                    address += 1;
+                }
+            }
+        }
+
+        let starts = lines.iter().map(Line::address).collect::<HashSet<_>>();
+        let labels = lines
+            .iter()
+            .filter_map(|line| match line {
+                Line::Instruction {
+                    opcode: JumpIfTrue,
+                    operands,
+                    ..
+                }
+                | Line::Instruction {
+                    opcode: JumpIfFalse,
+                    operands,
+                    ..
+                } => match operands[1] {
+                    (Immediate, target) if target >= 0 && starts.contains(&(target as usize)) => {
+                        Some(target as usize)
+                    }
+                    _ => None,
+                },
+                _ => None,
+            })
+            .map(|target| (target, format!("L{}", target)))
+            .collect::<BTreeMap<_, _>>();
+
+        let mut merged: Vec<Line> = Vec::new();
+        for line in lines {
+            if let (
+                Some(Line::Data { values, .. }),
+                Line::Data {
+                    address,
+                    values: next,
+                },
+            ) = (merged.last_mut(), &line)
+            {
+                if values.len() < DATA_PER_LINE && !labels.contains_key(address) {
+                    values.extend(next);
+                    continue;
+                }
+            }
+            merged.push(line);
+        }
+
+        Disassembly {
+            lines: merged,
+            labels,
+        }
+    }
+}
+
+impl Disassembly {
+    fn write_operand(
+        &self,
+        f: &mut fmt::Formatter,
+        (mode, value): (Mode, IntCodeCell),
+        is_target: bool,
+    ) -> fmt::Result {
+        match mode {
+            Position => write!(f, "[{}]", value),
+            Immediate => match self.labels.get(&(value as usize)) {
+                Some(label) if is_target && value >= 0 => write!(f, "#{}", label),
+                _ => write!(f, "#{}", value),
+            },
+            Relative if value < 0 => write!(f, "rb{}", value),
+            Relative => write!(f, "rb+{}", value),
+        }
+    }
+}
+
+impl fmt::Display for Disassembly {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let width = self
+            .lines
+            .last()
+            .map_or(1, |line| line.address().to_string().len());
+
+        for line in &self.lines {
+            if let Some(label) = self.labels.get(&line.address()) {
+                writeln!(f, "{}:", label)?;
+            }
+            write!(f, "    {:>width$}: ", line.address(), width = width)?;
+
+            match line {
+                Line::Instruction {
+                    opcode, operands, ..
+                } => {
+                    if operands.is_empty() {
+                        write!(f, "{}", opcode.mnemonic())?;
+                    } else {
+                        write!(f, "{:<5} ", opcode.mnemonic())?;
+                    }
+
+                    for (index, &operand) in operands.iter().enumerate() {
+                        if index > 0 {
+                            write!(f, ", ")?;
+                        }
+                        let is_target =
+                            index == 1 && (*opcode == JumpIfTrue || *opcode == JumpIfFalse);
+                        self.write_operand(f, operand, is_target)?;
+                    }
+                }
+                Line::Data { values, .. } => {
+                    write!(f, ".data ")?;
+                    for (index, value) in values.iter().enumerate() {
+                        if index > 0 {
+                            write!(f, ", ")?;
+                        }
+                        write!(f, "{}", value)?;
+                    }
+                }
+            }
+
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn disassemble(program: &str) -> String {
+        program
+            .parse::<IntCode>()
+            .unwrap()
+            .disassemble()
+            .to_string()
+    }
+
+    #[test]
+    fn listing() {
+        assert_eq!(
+            disassemble("3,12,6,12,15,1,13,14,13,4,13,99,-1,0,1,9"),
+            "     0: in    [12]
+     2: jf    [12], [15]
+     5: add   [13], [14], [13]
+     9: out   [13]
+    11: hlt
+    12: .data -1, 0, 1, 9
+"
+        );
+    }
+
+    #[test]
+    fn labels() {
+        assert_eq!(
+            disassemble("3,3,1105,-1,9,1101,0,0,12,4,12,99,1"),
+            "     0: in    [3]
+     2: jt    #-1, #L9
+     5: add   #0, #0, [12]
+L9:
+     9: out   [12]
+    11: hlt
+    12: .data 1
+"
+        );
+        assert_eq!(
+            disassemble("109,-1,204,1,1105,1,0,10099,42,0,0,0,0,0,0,0,0,0"),
+            "L0:
+     0: arb   #-1
+     2: out   rb+1
+     4: jt    #1, #L0
+     7: .data 10099, 42, 0, 0, 0, 0, 0, 0
+    15: .data 0, 0, 0
+"
+        );
+    }
+}
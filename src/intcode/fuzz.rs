@@ -0,0 +1,282 @@
+//! Differential fuzzing of the engines, for the `fuzz` binary.
+
+use super::snapshot::Snapshot;
+use super::*;
+use std::panic::{self, AssertUnwindSafe};
+
+const INSTRUCTION_BUDGET: u64 = 10_000;
+const MAX_EXTRA_CELLS: usize = 16 * PAGE_SIZE;
+const MAX_INSTRUCTIONS: u64 = 32;
+const MAX_INPUTS: u64 = 8;
+
+const OPCODES: [IntCodeCell; 10] = [1, 2, 3, 4, 5, 6, 7, 8, 9, 99];
+const EXTREMES: [IntCodeCell; 6] = [
+    IntCodeCell::MIN,
+    IntCodeCell::MIN + 1,
+    IntCodeCell::MAX,
+    1 << 32,
+    1 << 62,
+    -1,
+];
+
+pub trait Entropy {
+    fn next_u64(&mut self) -> u64;
+
+    fn below(&mut self, bound: u64) -> u64 {
+        self.next_u64() % bound
+    }
+
+    fn one_in(&mut self, odds: u64) -> bool {
+        self.below(odds) == 0
+    }
+}
+
+pub struct Xorshift(u64);
+
+impl Xorshift {
+    pub fn new(seed: u64) -> Self {
+        // The state must never be zero.
+        Self(seed | 1)
+    }
+}
+
+impl Entropy for Xorshift {
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+/// Fuzzer-provided data, a byte at a time. Reads as zeros once it runs out.
+pub struct Bytes<'a>(pub &'a [u8]);
+
+impl Entropy for Bytes<'_> {
+    fn next_u64(&mut self) -> u64 {
+        match self.0.split_first() {
+            Some((&byte, rest)) => {
+                self.0 = rest;
+                byte.into()
+            }
+            None => 0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Case {
+    pub program: Vec<IntCodeCell>,
+    pub inputs: Vec<IntCodeCell>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Failure {
+    Panicked(String),
+    EnginesDiffer,
+    // A clone of a machine went on to do something different from the original.
+    Nondeterministic,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Failure::Panicked(message) => write!(f, "panicked: {}", message),
+            Failure::EnginesDiffer => write!(f, "engines disagree"),
+            Failure::Nondeterministic => write!(f, "clone ran differently"),
+        }
+    }
+}
+
+impl std::error::Error for Failure {}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Outcome {
+    outputs: Vec<IntCodeCell>,
+    end: Result<StepResult, IntCodeError>,
+    state: Snapshot,
+}
+
+fn value(entropy: &mut impl Entropy, program_len: usize) -> IntCodeCell {
+    match entropy.below(8) {
+        0 => EXTREMES[entropy.below(EXTREMES.len() as u64) as usize],
+        1 | 2 => entropy.below(16) as IntCodeCell - 8,
+        // Mostly addresses in or just past the program.
+        _ => entropy.below(program_len as u64 + 8) as IntCodeCell,
+    }
+}
+
+fn mode(entropy: &mut impl Entropy, writes: bool) -> IntCodeCell {
+    if entropy.one_in(32) {
+        3 + entropy.below(7) as IntCodeCell
+    } else if writes && !entropy.one_in(16) {
+        [0, 2][entropy.below(2) as usize]
+    } else {
+        entropy.below(3) as IntCodeCell
+    }
+}
+
+impl Case {
+    /// Mostly valid instructions with in-range operands, plus the occasional bad
+    /// opcode, bad mode, extreme value or corrupted cell.
+    pub fn generate(entropy: &mut impl Entropy) -> Self {
+        let count = 1 + entropy.below(MAX_INSTRUCTIONS) as usize;
+        let program_len = count * 4;
+        let mut program = Vec::new();
+
+        for _ in 0..count {
+            let opcode = if entropy.one_in(16) {
+                value(entropy, program_len)
+            } else {
+                OPCODES[entropy.below(OPCODES.len() as u64) as usize]
+            };
+            let (parameters, write) = match Opcode::new(opcode) {
+                Some(opcode) => (
+                    opcode.parameters(),
+                    match opcode {
+                        Add | Multiply | LessThan | Equals => Some(2),
+                        Input => Some(0),
+                        _ => None,
+                    },
+                ),
+                None => (entropy.below(4) as usize, None),
+            };
+
+            let modes = (0..parameters).rev().fold(0, |modes, index| {
+                modes * 10 + mode(entropy, write == Some(index))
+            });
+            program.push(modes.wrapping_mul(100).wrapping_add(opcode));
+            for _ in 0..parameters {
+                program.push(value(entropy, program_len));
+            }
+        }
+
+        if entropy.one_in(4) {
+            for _ in 0..=entropy.below(3) {
+                let index = entropy.below(program.len() as u64) as usize;
+                program[index] = value(entropy, program_len);
+            }
+        }
+
+        let inputs = (0..entropy.below(MAX_INPUTS))
+            .map(|_| value(entropy, program_len))
+            .collect();
+        Self { program, inputs }
+    }
+
+    fn machine(&self, engine: Engine) -> IntCode {
+        let mut intcode = IntCode::new(self.program.clone());
+        intcode.set_engine(engine);
+        intcode.set_memory_limits(MemoryLimits {
+            max_address: None,
+            max_extra_cells: Some(MAX_EXTRA_CELLS),
+        });
+        intcode.set_run_limits(RunLimits {
+            instruction_budget: Some(INSTRUCTION_BUDGET),
+            ..RunLimits::default()
+        });
+        intcode
+    }
+
+    fn run(&self, mut intcode: IntCode) -> Outcome {
+        let mut inputs = self.inputs.iter();
+        let mut outputs = Vec::new();
+
+        let end = loop {
+            match intcode.resume() {
+                Ok(StepResult::NeedsInput) => match inputs.next() {
+                    Some(&value) => intcode.provide_input(value),
+                    None => break Ok(StepResult::NeedsInput),
+                },
+                Ok(StepResult::Output(value)) => outputs.push(value),
+                end => break end,
+            }
+        };
+
+        Outcome {
+            outputs,
+            end,
+            state: intcode.snapshot(),
+        }
+    }
+
+    pub fn check(&self) -> Result<(), Failure> {
+        let checked = panic::catch_unwind(AssertUnwindSafe(|| {
+            let interpreted = self.run(self.machine(Engine::Interpreter));
+            if self.run(self.machine(Engine::Compiled)) != interpreted {
+                return Err(Failure::EnginesDiffer);
+            }
+
+            // Part way in, so the clone also carries the compiled engine's decoded code.
+            let mut original = self.machine(Engine::Compiled);
+            for _ in 0..self.program.len() {
+                if original.step() != Ok(None) {
+                    break;
+                }
+            }
+            let clone = original.clone();
+            if self.run(clone) != self.run(original) {
+                return Err(Failure::Nondeterministic);
+            }
+            Ok(())
+        }));
+
+        checked.unwrap_or_else(|payload| {
+            let message = match payload.downcast::<String>() {
+                Ok(message) => *message,
+                Err(payload) => match payload.downcast::<&str>() {
+                    Ok(message) => message.to_string(),
+                    Err(_) => "unknown panic".to_string(),
+                },
+            };
+            Err(Failure::Panicked(message))
+        })
+    }
+}
+
+impl fmt::Display for Case {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let join = |values: &[IntCodeCell]| {
+            values
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        write!(
+            f,
+            "program {}\ninputs {}",
+            join(&self.program),
+            join(&self.inputs)
+        )
+    }
+}
+
+/// The whole body of a cargo-fuzz target: `check_bytes(data).unwrap()`.
+pub fn check_bytes(data: &[u8]) -> Result<(), Failure> {
+    Case::generate(&mut Bytes(data)).check()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn random_programs() {
+        let mut rng = Xorshift::new(2019);
+        for _ in 0..500 {
+            let case = Case::generate(&mut rng);
+            assert_eq!(case.check(), Ok(()), "\n{}", case);
+        }
+    }
+
+    #[test]
+    fn fuzzer_bytes() {
+        assert_eq!(check_bytes(&[]), Ok(()));
+        assert_eq!(check_bytes(&[0xff; 64]), Ok(()));
+        assert_eq!(
+            check_bytes(b"\x03\x01\x02\x03\x04\x05\x06\x07\x08\x09"),
+            Ok(())
+        );
+    }
+}
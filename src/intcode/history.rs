@@ -0,0 +1,360 @@
+//! Stepping a machine backwards.
+
+use super::snapshot::Snapshot;
+use super::*;
+use std::collections::HashSet;
+
+// Steps between full checkpoints. Only the undo log since the latest checkpoint is
+// kept; older ones are rebuilt by running forward from their checkpoint.
+const CHECKPOINT_INTERVAL: usize = 4096;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+/// A write found in the history.
+pub struct PastWrite {
+    /// Number of steps that had run before the writing instruction.
+    pub step: u64,
+    /// The instruction that wrote.
+    pub pc: usize,
+    /// What the cell held before.
+    pub old: IntCodeCell,
+}
+
+#[derive(Copy, Clone)]
+struct Undo {
+    pc: usize,
+    relative_base: IntCodeCell,
+    write: Option<(usize, IntCodeCell)>,
+    input: Option<IntCodeCell>,
+}
+
+struct Segment {
+    start: u64,
+    checkpoint: Snapshot,
+    // Inputs consumed and cells written during the segment, so it can be rerun and
+    // skipped over when looking for writes.
+    inputs: Vec<IntCodeCell>,
+    written: HashSet<usize>,
+    undo: Vec<Undo>,
+}
+
+impl Segment {
+    fn new(start: u64, intcode: &IntCode) -> Self {
+        Self {
+            start,
+            checkpoint: intcode.snapshot(),
+            inputs: Vec::new(),
+            written: HashSet::new(),
+            undo: Vec::new(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct LastEvent(Option<TraceEvent>);
+
+impl Tracer for LastEvent {
+    fn trace(&mut self, event: &TraceEvent) {
+        self.0 = Some(*event);
+    }
+}
+
+/// Lets a machine be stepped backwards, as long as every step goes through `step`.
+/// Anything else that changes the machine, other than queueing input, should be
+/// followed by `checkpoint`.
+pub struct History {
+    segments: Vec<Segment>,
+    steps: u64,
+}
+
+impl History {
+    /// Starts recording from the machine as it is.
+    pub fn new(intcode: &IntCode) -> Self {
+        Self {
+            segments: vec![Segment::new(0, intcode)],
+            steps: 0,
+        }
+    }
+
+    /// Steps recorded and not undone. Waiting for input and halting don't count.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Records the machine as it is now. Stepping back past this point restores the
+    /// machine as it was here, rather than undoing whatever changed it outside `step`.
+    pub fn checkpoint(&mut self, intcode: &IntCode) {
+        let segment = self.segments.last_mut().unwrap();
+        if segment.start == self.steps && segment.undo.is_empty() {
+            segment.checkpoint = intcode.snapshot();
+            return;
+        }
+
+        segment.undo = Vec::new();
+        self.segments.push(Segment::new(self.steps, intcode));
+    }
+
+    /// Runs one instruction, as `IntCode::step` does, recording how to undo it.
+    pub fn step(&mut self, intcode: &mut IntCode) -> Result<Option<StepResult>, IntCodeError> {
+        if self.segments.last().unwrap().undo.len() >= CHECKPOINT_INTERVAL {
+            self.checkpoint(intcode);
+        }
+
+        let segment = self.segments.last_mut().unwrap();
+        let (result, undo) = match record(intcode)? {
+            (result, Some(undo)) => (result, undo),
+            (result, None) => return Ok(result),
+        };
+
+        if let Some((address, _)) = undo.write {
+            segment.written.insert(address);
+        }
+        segment.inputs.extend(undo.input);
+        segment.undo.push(undo);
+        self.steps += 1;
+        Ok(result)
+    }
+
+    /// Undoes the latest step. Returns false if there's nothing left to undo.
+    pub fn step_back(&mut self, intcode: &mut IntCode) -> bool {
+        while self.segments.last().unwrap().undo.is_empty() {
+            if self.segments.len() == 1 {
+                return false;
+            }
+            let boundary = self.segments.pop().unwrap();
+            self.rebuild(intcode, &boundary);
+        }
+
+        let segment = self.segments.last_mut().unwrap();
+        let undo = segment.undo.pop().unwrap();
+        intcode.pc = undo.pc;
+        intcode.relative_base = undo.relative_base;
+        if let Some((address, old)) = undo.write {
+            intcode.memory[address] = old;
+            intcode.code.invalidate(address);
+        }
+        if let Some(value) = undo.input {
+            segment.inputs.pop();
+            intcode.inputs.push_front(value);
+        }
+        self.steps -= 1;
+        true
+    }
+
+    /// Steps back until `stop` holds for the machine, returning false if history ran
+    /// out first.
+    pub fn run_back_until(
+        &mut self,
+        intcode: &mut IntCode,
+        mut stop: impl FnMut(&IntCode) -> bool,
+    ) -> bool {
+        while self.step_back(intcode) {
+            if stop(intcode) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// The latest recorded write to `address`, if any step still recorded made one.
+    pub fn last_write(&self, address: usize) -> Option<PastWrite> {
+        let find = |start: u64, undo: &[Undo]| {
+            undo.iter()
+                .enumerate()
+                .rev()
+                .find(|(_, undo)| matches!(undo.write, Some((written, _)) if written == address))
+                .map(|(index, undo)| PastWrite {
+                    step: start + index as u64,
+                    pc: undo.pc,
+                    old: undo.write.unwrap().1,
+                })
+        };
+
+        let (current, earlier) = self.segments.split_last().unwrap();
+        find(current.start, &current.undo).or_else(|| {
+            earlier
+                .iter()
+                .zip(&self.segments[1..])
+                .rev()
+                .filter(|(segment, _)| segment.written.contains(&address))
+                .find_map(|(segment, next)| {
+                    let (_, undo) = self.rerun(segment, next.start);
+                    find(segment.start, &undo)
+                })
+        })
+    }
+
+    fn rerun(&self, segment: &Segment, end: u64) -> (IntCode, Vec<Undo>) {
+        let mut intcode = IntCode::restore(&segment.checkpoint);
+        intcode.inputs = segment.inputs.iter().copied().collect();
+
+        let mut undo = Vec::new();
+        while undo.len() < (end - segment.start) as usize {
+            // These exact steps already succeeded once.
+            if let (_, Some(entry)) = record(&mut intcode).unwrap() {
+                undo.push(entry);
+            }
+        }
+        (intcode, undo)
+    }
+
+    // Puts the machine at the end of the last segment, just before `boundary` started.
+    fn rebuild(&mut self, intcode: &mut IntCode, boundary: &Segment) {
+        let (mut rebuilt, undo) = self.rerun(self.segments.last().unwrap(), boundary.start);
+        self.segments.last_mut().unwrap().undo = undo;
+
+        // Input queued since the checkpoint was taken is still waiting to be read.
+        rebuilt.inputs = std::mem::take(&mut intcode.inputs);
+        rebuilt.memory.limits = intcode.memory.limits;
+        rebuilt.limits = intcode.limits.clone();
+        rebuilt.set_engine(intcode.engine());
+        *intcode = rebuilt;
+    }
+}
+
+// Steps the machine, returning what it would take to undo the step if it changed anything.
+fn record(intcode: &mut IntCode) -> Result<(Option<StepResult>, Option<Undo>), IntCodeError> {
+    let (pc, relative_base) = (intcode.pc, intcode.relative_base);
+    let mut last = LastEvent::default();
+    let result = intcode.step_traced(&mut last)?;
+
+    let event = match (result, last.0) {
+        (Some(StepResult::Halted), _) | (_, None) => return Ok((result, None)),
+        (_, Some(event)) => event,
+    };
+    let write = event.write.map(|write| (write.address, write.old));
+    let input = match event.opcode {
+        Input => event.write.map(|write| write.new),
+        _ => None,
+    };
+
+    Ok((
+        result,
+        Some(Undo {
+            pc,
+            relative_base,
+            write,
+            input,
+        }),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn countdown() -> IntCode {
+        IntCode::assemble(
+            "
+                in    [counter]
+            loop:
+                add   [counter], #-1, [counter]
+                arb   #1
+                jt    [counter], #loop
+                out   [counter]
+                hlt
+            counter: .data 0
+            ",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn step_back() {
+        let mut intcode = countdown();
+        intcode.provide_input(3);
+        let mut history = History::new(&intcode);
+        let mut states = vec![intcode.snapshot()];
+
+        while history.step(&mut intcode).unwrap() != Some(StepResult::Halted) {
+            states.push(intcode.snapshot());
+        }
+        assert_eq!(history.steps(), 11);
+        assert_eq!(states.len(), 12);
+
+        while let Some(state) = states.pop() {
+            assert_eq!(intcode.snapshot(), state);
+            assert_eq!(history.step_back(&mut intcode), !states.is_empty());
+        }
+        assert_eq!(intcode.pending_inputs().collect::<Vec<_>>(), vec![3]);
+    }
+
+    #[test]
+    fn checkpoints() {
+        let mut intcode = countdown();
+        intcode.provide_input(5000);
+        let mut history = History::new(&intcode);
+        let start = intcode.snapshot();
+
+        while history.step(&mut intcode).unwrap() != Some(StepResult::Halted) {}
+        let end = intcode.snapshot();
+        assert_eq!(history.steps(), 15_002);
+        assert_eq!(history.segments.len(), 4);
+
+        assert_eq!(
+            history.last_write(14),
+            Some(PastWrite {
+                step: 14_998,
+                pc: 2,
+                old: 1
+            })
+        );
+        assert!(history.run_back_until(&mut intcode, |intcode| intcode.cell(14) == 4000));
+        assert_eq!(history.steps(), 3001);
+        assert_eq!(intcode.relative_base(), 1000);
+        assert_eq!(
+            history.last_write(14),
+            Some(PastWrite {
+                step: 2998,
+                pc: 2,
+                old: 4001
+            })
+        );
+        assert_eq!(history.last_write(15), None);
+
+        // Stepping forward again replaces the old future.
+        while history.step(&mut intcode).unwrap() != Some(StepResult::Halted) {}
+        assert_eq!(intcode.snapshot(), end);
+        assert!(!history.run_back_until(&mut intcode, |_| false));
+        assert_eq!(intcode.snapshot(), start);
+    }
+
+    #[test]
+    fn late_input() {
+        let mut intcode = IntCode::assemble(
+            "
+                in    [counter]
+            loop:
+                add   [counter], #-1, [counter]
+                jt    [counter], #loop
+                in    [extra]
+                out   [extra]
+                hlt
+            counter: .data 0
+            extra:   .data 0
+            ",
+        )
+        .unwrap();
+        intcode.provide_input(3000);
+        let mut history = History::new(&intcode);
+
+        while history.step(&mut intcode).unwrap() != Some(StepResult::NeedsInput) {}
+        assert!(history.segments.len() > 1);
+
+        // Queued without a checkpoint, as the debugger's `in` may.
+        intcode.provide_input(7);
+        let mut outputs = Vec::new();
+        loop {
+            match history.step(&mut intcode).unwrap() {
+                Some(StepResult::Output(value)) => outputs.push(value),
+                Some(StepResult::Halted) => break,
+                _ => (),
+            }
+        }
+        assert_eq!(outputs, vec![7]);
+
+        assert!(!history.run_back_until(&mut intcode, |_| false));
+        assert_eq!(intcode.pending_inputs().collect::<Vec<_>>(), vec![3000, 7]);
+        while history.step(&mut intcode).unwrap() != Some(StepResult::Halted) {}
+        assert_eq!(intcode.cell(intcode.program().len() - 1), 7);
+    }
+}
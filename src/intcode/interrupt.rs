@@ -0,0 +1,116 @@
+//! Stopping a run from outside the program.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Stops every run holding a clone of it, from any thread.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// A token that hasn't been cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs notice within about a thousand instructions. There's no undoing it.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether `cancel` has been called on this token or any clone of it.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Which run limit stopped a machine.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Interrupt {
+    /// The instruction budget ran out.
+    BudgetExhausted,
+    /// The deadline passed.
+    DeadlineExceeded,
+    /// The cancellation token was cancelled.
+    Cancelled,
+}
+
+/// Limits on how long a machine may run. The default is no limit at all.
+///
+/// ```
+/// use aoc2019::intcode::interrupt::{Interrupt, RunLimits};
+/// use aoc2019::intcode::{IntCode, StepResult};
+///
+/// let mut intcode = "1105,1,0".parse::<IntCode>().unwrap();
+/// intcode.set_run_limits(RunLimits::default().with_instruction_budget(100));
+/// assert_eq!(
+///     intcode.resume(),
+///     Ok(StepResult::Interrupted(Interrupt::BudgetExhausted))
+/// );
+/// ```
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub struct RunLimits {
+    /// Instructions left to run. It counts down as the machine runs.
+    pub instruction_budget: Option<u64>,
+    /// Checked every thousand or so instructions, so runs can overshoot it a little.
+    pub deadline: Option<Instant>,
+    /// Checked as often as the deadline.
+    pub cancellation: Option<CancellationToken>,
+}
+
+impl RunLimits {
+    /// These limits, with an instruction budget of `budget`.
+    pub fn with_instruction_budget(mut self, budget: u64) -> Self {
+        self.instruction_budget = Some(budget);
+        self
+    }
+
+    /// These limits, with a deadline.
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// These limits, stopping when `token` is cancelled.
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    pub(super) fn is_unlimited(&self) -> bool {
+        self.instruction_budget.is_none() && self.deadline.is_none() && self.cancellation.is_none()
+    }
+
+    pub(super) fn check_budget(&self) -> Option<Interrupt> {
+        match self.instruction_budget {
+            Some(0) => Some(Interrupt::BudgetExhausted),
+            _ => None,
+        }
+    }
+
+    pub(super) fn check_clock(&self) -> Option<Interrupt> {
+        if self
+            .cancellation
+            .as_ref()
+            .is_some_and(CancellationToken::is_cancelled)
+        {
+            Some(Interrupt::Cancelled)
+        } else if self
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            Some(Interrupt::DeadlineExceeded)
+        } else {
+            None
+        }
+    }
+
+    pub(super) fn spend(&mut self) {
+        if let Some(budget) = &mut self.instruction_budget {
+            *budget -= 1;
+        }
+    }
+}
@@ -0,0 +1,491 @@
+//! Reusing the results of runs that were already made.
+
+use super::session::{fnv, FNV_OFFSET};
+use super::*;
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+const HEADER: &str = "intcode-memo 3";
+
+static SHARED: Mutex<Option<Memo>> = Mutex::new(None);
+// Set once there's a shared memo, so runs without one never touch the lock.
+static SHARING: AtomicBool = AtomicBool::new(false);
+
+/// What a program left behind once it halted.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RunResult {
+    /// Everything the program output.
+    pub outputs: Vec<IntCodeCell>,
+    /// Final program memory, as `run_no_io` returns it.
+    pub memory: Vec<IntCodeCell>,
+}
+
+// Everything a builder starts a run with, beyond the program itself. Builders with
+// memory or run limits are never memoized, so those don't need to be here, and
+// every engine must give the same results, so neither does the engine.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct RunKey {
+    patches: Vec<(usize, IntCodeCell)>,
+    inputs: Vec<IntCodeCell>,
+}
+
+impl RunKey {
+    fn new(builder: &IntCodeBuilder) -> Self {
+        Self {
+            patches: builder.patches().to_vec(),
+            inputs: builder.queued_inputs().to_vec(),
+        }
+    }
+}
+
+// Results for one program, and where to record new ones.
+#[derive(Default)]
+struct ProgramResults {
+    results: HashMap<RunKey, Arc<RunResult>>,
+    file: Option<Arc<Mutex<File>>>,
+}
+
+// A result that still has to be written out, once no lock on the memo is held.
+struct PendingWrite {
+    file: Arc<Mutex<File>>,
+    line: String,
+}
+
+impl PendingWrite {
+    fn write(self) {
+        // The disk cache only saves time, so failing to write it isn't an error.
+        let _ = writeln!(self.file.lock().unwrap(), "{}", self.line);
+    }
+}
+
+enum Lookup {
+    Hit(Arc<RunResult>),
+    Miss,
+    // Nothing is known about the program until its file has been read.
+    Unloaded(PathBuf),
+}
+
+/// Results of runs that halted, keyed on the program, its patches and its input. Builders with memory or run limits always run, since those can stop a
+/// program that would otherwise halt.
+///
+/// With a directory, results are also kept on disk, one file per program, so later
+/// processes can reuse them.
+#[derive(Default)]
+pub struct Memo {
+    programs: HashMap<Vec<IntCodeCell>, ProgramResults>,
+    dir: Option<PathBuf>,
+    hits: u64,
+    misses: u64,
+}
+
+/// The hash memo files are named by. Two programs can share one.
+pub fn program_hash(program: &[IntCodeCell]) -> u64 {
+    let hash = fnv(FNV_OFFSET, program.len() as IntCodeCell);
+    program.iter().fold(hash, |hash, &value| fnv(hash, value))
+}
+
+fn run(builder: &IntCodeBuilder) -> Result<RunResult, IntCodeError> {
+    let mut intcode = builder.clone().build();
+    let mut outputs = Vec::new();
+    // Queued inputs are all it gets.
+    intcode.run_with_fns(|| None, |value| outputs.push(value))?;
+    Ok(RunResult {
+        outputs,
+        memory: intcode.program().to_vec(),
+    })
+}
+
+impl Memo {
+    /// A memo kept in memory only.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A memo that also keeps results in `dir`, creating it if needed.
+    pub fn with_dir(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir: Some(dir),
+            ..Self::default()
+        })
+    }
+
+    /// Makes `IntCodeBuilder::run_memoized` go through `memo` from now on.
+    pub fn set_shared(memo: Memo) {
+        *SHARED.lock().unwrap() = Some(memo);
+        SHARING.store(true, Ordering::Release);
+    }
+
+    /// How many lookups found a result.
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    /// How many lookups didn't.
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    /// The result of running `builder`, if it has been memoized.
+    pub fn get(&mut self, builder: &IntCodeBuilder) -> Option<Arc<RunResult>> {
+        loop {
+            match self.lookup(builder) {
+                Lookup::Hit(result) => return Some(result),
+                Lookup::Miss => return None,
+                Lookup::Unloaded(path) => {
+                    self.programs
+                        .insert(builder.program().to_vec(), load(&path, builder.program()));
+                }
+            }
+        }
+    }
+
+    /// Memoizes `result` as what `builder` runs to, writing it to disk if there's
+    /// a directory.
+    pub fn insert(&mut self, builder: &IntCodeBuilder, result: RunResult) -> Arc<RunResult> {
+        let (result, pending) = self.store(builder, result);
+        if let Some(pending) = pending {
+            pending.write();
+        }
+        result
+    }
+
+    /// Runs `builder` to completion on its queued input, unless the result is already
+    /// known. Failed runs aren't kept.
+    pub fn run(&mut self, builder: &IntCodeBuilder) -> Result<Arc<RunResult>, IntCodeError> {
+        if builder.is_limited() {
+            return run(builder).map(Arc::new);
+        }
+        match self.get(builder) {
+            Some(result) => Ok(result),
+            None => Ok(self.insert(builder, run(builder)?)),
+        }
+    }
+
+    fn lookup(&mut self, builder: &IntCodeBuilder) -> Lookup {
+        let program = match self.programs.get(builder.program()) {
+            Some(program) => program,
+            None => match &self.dir {
+                Some(dir) => return Lookup::Unloaded(path(dir, builder.program())),
+                None => {
+                    self.misses += 1;
+                    return Lookup::Miss;
+                }
+            },
+        };
+
+        match program.results.get(&RunKey::new(builder)) {
+            Some(result) => {
+                self.hits += 1;
+                Lookup::Hit(Arc::clone(result))
+            }
+            None => {
+                self.misses += 1;
+                Lookup::Miss
+            }
+        }
+    }
+
+    fn store(
+        &mut self,
+        builder: &IntCodeBuilder,
+        result: RunResult,
+    ) -> (Arc<RunResult>, Option<PendingWrite>) {
+        let program = self.programs.entry(builder.program().to_vec()).or_default();
+        let key = RunKey::new(builder);
+        let pending = program.file.as_ref().map(|file| PendingWrite {
+            file: Arc::clone(file),
+            line: line(builder.program(), &key, &result),
+        });
+
+        let result = Arc::new(result);
+        program.results.insert(key, Arc::clone(&result));
+        (result, pending)
+    }
+}
+
+impl IntCodeBuilder {
+    /// Runs to completion on the queued input, through the shared memo if one has
+    /// been set with `Memo::set_shared`.
+    pub fn run_memoized(&self) -> Result<Arc<RunResult>, IntCodeError> {
+        if self.is_limited() || !SHARING.load(Ordering::Acquire) {
+            return run(self).map(Arc::new);
+        }
+
+        // The memo is only locked to look things up and store them. Running and file
+        // access happen without it, so parallel runs can overlap.
+        let shared = || SHARED.lock().unwrap();
+        loop {
+            let lookup = match shared().as_mut() {
+                Some(memo) => memo.lookup(self),
+                None => return run(self).map(Arc::new),
+            };
+            match lookup {
+                Lookup::Hit(result) => return Ok(result),
+                Lookup::Miss => break,
+                Lookup::Unloaded(path) => {
+                    let loaded = load(&path, self.program());
+                    if let Some(memo) = shared().as_mut() {
+                        // Another thread may have loaded it first.
+                        memo.programs
+                            .entry(self.program().to_vec())
+                            .or_insert(loaded);
+                    }
+                }
+            }
+        }
+
+        let result = run(self)?;
+        let stored = shared()
+            .as_mut()
+            .map(|memo| memo.store(self, result.clone()));
+        match stored {
+            Some((result, pending)) => {
+                if let Some(pending) = pending {
+                    pending.write();
+                }
+                Ok(result)
+            }
+            None => Ok(Arc::new(result)),
+        }
+    }
+}
+
+// Named by hash, but each file starts with the whole program, so a collision or a
+// stale file is never mistaken for a match.
+fn path(dir: &Path, program: &[IntCodeCell]) -> PathBuf {
+    dir.join(format!("{:016x}.memo", program_hash(program)))
+}
+
+fn join(values: impl Iterator<Item = String>) -> String {
+    values.collect::<Vec<_>>().join(",")
+}
+
+fn join_values(values: &[IntCodeCell]) -> String {
+    join(values.iter().map(ToString::to_string))
+}
+
+fn join_pairs(pairs: impl Iterator<Item = (usize, IntCodeCell)>) -> String {
+    join(pairs.map(|(address, value)| format!("{}={}", address, value)))
+}
+
+fn split<T>(field: &str, parse: impl Fn(&str) -> Option<T>) -> Option<Vec<T>> {
+    if field.is_empty() {
+        return Some(Vec::new());
+    }
+    field.split(',').map(parse).collect()
+}
+
+fn parse_pair(pair: &str) -> Option<(usize, IntCodeCell)> {
+    let mut parts = pair.splitn(2, '=');
+    Some((parts.next()?.parse().ok()?, parts.next()?.parse().ok()?))
+}
+
+// One line per run: patches, inputs, outputs and the cells that differ from the
+// program at the end, separated by tabs.
+fn line(program: &[IntCodeCell], key: &RunKey, result: &RunResult) -> String {
+    let changes = result
+        .memory
+        .iter()
+        .zip(program)
+        .enumerate()
+        .filter(|(_, (new, old))| new != old)
+        .map(|(address, (&new, _))| (address, new));
+    format!(
+        "{}\t{}\t{}\t{}",
+        join_pairs(key.patches.iter().copied()),
+        join_values(&key.inputs),
+        join_values(&result.outputs),
+        join_pairs(changes)
+    )
+}
+
+fn parse_line(program: &[IntCodeCell], line: &str) -> Option<(RunKey, Arc<RunResult>)> {
+    let fields = line.split('\t').collect::<Vec<_>>();
+    let (patches, inputs, outputs, changes) = match fields[..] {
+        [patches, inputs, outputs, changes] => (patches, inputs, outputs, changes),
+        _ => return None,
+    };
+
+    let mut memory = program.to_vec();
+    for (address, value) in split(changes, parse_pair)? {
+        *memory.get_mut(address)? = value;
+    }
+    let key = RunKey {
+        patches: split(patches, parse_pair)?,
+        inputs: split(inputs, |value| value.parse().ok())?,
+    };
+    let outputs = split(outputs, |value| value.parse().ok())?;
+    Some((key, Arc::new(RunResult { outputs, memory })))
+}
+
+// Anything unreadable is skipped, so a damaged cache just means more running. A
+// file made for another program is left alone, and nothing new is written to it.
+fn load(path: &Path, program: &[IntCodeCell]) -> ProgramResults {
+    let text = fs::read_to_string(path).unwrap_or_default();
+    let mut lines = text.lines();
+    let program_line = format!("program {}", join_values(program));
+
+    let fresh = match (lines.next(), lines.next()) {
+        (Some(HEADER), Some(first)) if first == program_line => false,
+        (Some(HEADER), Some(_)) => return ProgramResults::default(),
+        // Missing, empty or in an old format.
+        _ => true,
+    };
+
+    let file = if fresh {
+        File::create(path).and_then(|mut file| {
+            writeln!(file, "{}\n{}", HEADER, program_line)?;
+            Ok(file)
+        })
+    } else {
+        OpenOptions::new()
+            .append(true)
+            .open(path)
+            .and_then(|mut file| {
+                // Finish off a line torn by an interrupted write.
+                if !text.ends_with('\n') {
+                    writeln!(file)?;
+                }
+                Ok(file)
+            })
+    };
+
+    let results = if fresh {
+        HashMap::new()
+    } else {
+        lines.filter_map(|line| parse_line(program, line)).collect()
+    };
+    ProgramResults {
+        results,
+        file: file.ok().map(|file| Arc::new(Mutex::new(file))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Adds its two inputs into cell 0, then outputs the patched cell 12.
+    const PROGRAM: &str = "3,13,3,14,1,13,14,0,4,12,99,0,0,0,0";
+
+    #[test]
+    fn memoizes() {
+        let program = PROGRAM.parse::<IntCodeBuilder>().unwrap();
+        let mut memo = Memo::new();
+
+        let builder = program.clone().patch(12, 7).inputs(vec![2, 3]);
+        let result = memo.run(&builder).unwrap();
+        assert_eq!(result.outputs, vec![7]);
+        assert_eq!(result.memory[0], 5);
+        assert!(Arc::ptr_eq(&memo.run(&builder).unwrap(), &result));
+
+        assert_eq!(
+            memo.run(&program.clone().patch(12, 8).inputs(vec![2, 3]))
+                .unwrap()
+                .outputs,
+            vec![8]
+        );
+        assert_eq!(
+            memo.run(&program.clone().patch(12, 7).inputs(vec![3, 3]))
+                .unwrap()
+                .memory[0],
+            6
+        );
+        assert_eq!((memo.hits(), memo.misses()), (1, 3));
+
+        // Failed runs aren't kept.
+        assert!(memo.run(&program.clone().input(2)).is_err());
+        assert!(memo.run(&program.input(2)).is_err());
+        assert_eq!((memo.hits(), memo.misses()), (1, 5));
+    }
+
+    #[test]
+    fn engines_and_limits() {
+        let builder = PROGRAM
+            .parse::<IntCodeBuilder>()
+            .unwrap()
+            .patch(12, 7)
+            .inputs(vec![2, 3]);
+        let mut memo = Memo::new();
+
+        // Engines give the same results, so they share them.
+        let result = memo
+            .run(&builder.clone().engine(Engine::Interpreter))
+            .unwrap();
+        assert!(Arc::ptr_eq(
+            &memo.run(&builder.clone().engine(Engine::Compiled)).unwrap(),
+            &result
+        ));
+        assert_eq!((memo.hits(), memo.misses()), (1, 1));
+
+        // A budget too small to finish fails every time, rather than reusing the
+        // unlimited run.
+        let limited = builder.run_limits(RunLimits {
+            instruction_budget: Some(2),
+            ..RunLimits::default()
+        });
+        assert!(memo.run(&limited).is_err());
+        assert!(memo.run(&limited).is_err());
+        assert_eq!((memo.hits(), memo.misses()), (1, 1));
+    }
+
+    #[test]
+    fn on_disk() {
+        let dir = std::env::temp_dir().join(format!("intcode-memo-{}", std::process::id()));
+        let program = PROGRAM.parse::<IntCodeBuilder>().unwrap();
+        let builders = [
+            program.clone().patch(12, -7).inputs(vec![2, 3]),
+            program.clone().patch(20, 1).inputs(vec![-4, 4]),
+        ];
+
+        let mut memo = Memo::with_dir(&dir).unwrap();
+        let results = builders
+            .iter()
+            .map(|builder| memo.run(builder).unwrap())
+            .collect::<Vec<_>>();
+
+        // A torn final line from an interrupted write.
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(path(&dir, program.program()))
+            .unwrap();
+        write!(file, "12=5\t1,").unwrap();
+
+        let mut memo = Memo::with_dir(&dir).unwrap();
+        for (builder, result) in builders.iter().zip(&results) {
+            assert_eq!(memo.get(builder).as_ref(), Some(result));
+        }
+        assert_eq!(memo.get(&program.patch(12, 5).input(1)), None);
+        assert_eq!((memo.hits(), memo.misses()), (2, 1));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn other_program() {
+        let dir = std::env::temp_dir().join(format!("intcode-memo-other-{}", std::process::id()));
+        let program = PROGRAM.parse::<IntCodeBuilder>().unwrap();
+        let builder = program.clone().patch(12, 7).inputs(vec![2, 3]);
+        let mut memo = Memo::with_dir(&dir).unwrap();
+        memo.run(&builder).unwrap();
+
+        // As if another program's hash had collided with this one.
+        let path = path(&dir, program.program());
+        let text = fs::read_to_string(&path).unwrap();
+        let text = text.replacen("program 3,", "program 4,", 1);
+        fs::write(&path, &text).unwrap();
+
+        let mut memo = Memo::with_dir(&dir).unwrap();
+        assert_eq!(memo.get(&builder), None);
+        memo.run(&builder).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), text);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
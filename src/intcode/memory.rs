@@ -0,0 +1,242 @@
+//! Machine memory, and limits on how much of it a program may use.
+
+use super::cell::Cell;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::ops::{Index, IndexMut};
+use std::sync::Arc;
+
+/// Memory past the end of the program is allocated this many cells at a time.
+pub const PAGE_SIZE: usize = 1024;
+// Pages below this index live in a flat table so the common case skips hashing.
+const DENSE_PAGES: usize = 1024;
+
+type Page<C> = Box<[C; PAGE_SIZE]>;
+
+/// Limits on the memory a program may use. The default is no limit at all.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct MemoryLimits {
+    /// The highest address the program may read or write.
+    pub max_address: Option<usize>,
+    /// How many cells may be allocated past the end of the program. Memory comes in
+    /// pages of [`PAGE_SIZE`] cells, so this is rounded up to whole pages.
+    pub max_extra_cells: Option<usize>,
+}
+
+impl MemoryLimits {
+    /// These limits, with `max_address` as the highest usable address.
+    pub fn with_max_address(mut self, max_address: usize) -> Self {
+        self.max_address = Some(max_address);
+        self
+    }
+
+    /// These limits, allowing `max_extra_cells` past the end of the program, rounded
+    /// up to whole pages.
+    pub fn with_max_extra_cells(mut self, max_extra_cells: usize) -> Self {
+        self.max_extra_cells = Some(max_extra_cells);
+        self
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(super) enum MemoryFault {
+    OutOfRange,
+    BudgetExceeded,
+}
+
+#[derive(Clone)]
+pub(super) struct Memory<C> {
+    pub(super) starting_memory: Vec<C>,
+    // The program as it was loaded, before anything wrote to it.
+    pub(super) original: Arc<[C]>,
+    dense_pages: Vec<Option<Page<C>>>,
+    sparse_pages: HashMap<usize, Page<C>>,
+    page_count: usize,
+    pub(super) limits: MemoryLimits,
+    // Unallocated cells read as this.
+    zero: C,
+}
+
+fn new_page<C: Cell>() -> Page<C> {
+    match vec![C::default(); PAGE_SIZE].into_boxed_slice().try_into() {
+        Ok(page) => page,
+        Err(_) => unreachable!(),
+    }
+}
+
+impl<C: Cell> Memory<C> {
+    pub(super) fn new(starting_memory: Vec<C>) -> Self {
+        Self {
+            original: starting_memory.as_slice().into(),
+            starting_memory,
+            dense_pages: Vec::new(),
+            sparse_pages: HashMap::new(),
+            page_count: 0,
+            limits: MemoryLimits::default(),
+            zero: C::default(),
+        }
+    }
+
+    pub(super) fn extra_cells(&self) -> usize {
+        self.page_count * PAGE_SIZE
+    }
+
+    #[inline]
+    fn page(&self, page: usize) -> Option<&Page<C>> {
+        if page < DENSE_PAGES {
+            self.dense_pages.get(page)?.as_ref()
+        } else {
+            self.sparse_pages.get(&page)
+        }
+    }
+
+    fn page_mut(&mut self, page: usize) -> &mut Page<C> {
+        let slot = if page < DENSE_PAGES {
+            if page >= self.dense_pages.len() {
+                self.dense_pages.resize_with(page + 1, || None);
+            }
+            &mut self.dense_pages[page]
+        } else {
+            match self.sparse_pages.entry(page) {
+                Entry::Occupied(entry) => return entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    self.page_count += 1;
+                    return entry.insert(new_page());
+                }
+            }
+        };
+
+        if slot.is_none() {
+            self.page_count += 1;
+        }
+        slot.get_or_insert_with(new_page)
+    }
+
+    pub(super) fn extra(&self) -> impl Iterator<Item = (usize, C)> + '_ {
+        let program_len = self.starting_memory.len();
+        let dense = self
+            .dense_pages
+            .iter()
+            .enumerate()
+            .filter_map(|(page, cells)| Some((page, cells.as_ref()?)));
+        let sparse = self.sparse_pages.iter().map(|(&page, cells)| (page, cells));
+
+        dense.chain(sparse).flat_map(move |(page, cells)| {
+            cells
+                .iter()
+                .enumerate()
+                .map(move |(offset, value)| (page * PAGE_SIZE + offset, value))
+                .filter(move |&(address, value)| address >= program_len && !value.is_zero())
+                .map(|(address, value)| (address, value.clone()))
+        })
+    }
+
+    #[inline]
+    pub(super) fn read(&self, address: usize) -> Result<&C, MemoryFault> {
+        match self.limits.max_address {
+            Some(max) if address > max => Err(MemoryFault::OutOfRange),
+            _ => Ok(&self[address]),
+        }
+    }
+
+    #[inline]
+    pub(super) fn write(&mut self, address: usize, value: C) -> Result<(), MemoryFault> {
+        if let Some(max) = self.limits.max_address {
+            if address > max {
+                return Err(MemoryFault::OutOfRange);
+            }
+        }
+
+        if address >= self.starting_memory.len() && self.page(address / PAGE_SIZE).is_none() {
+            if let Some(budget) = self.limits.max_extra_cells {
+                if self.page_count >= budget.div_ceil(PAGE_SIZE) {
+                    return Err(MemoryFault::BudgetExceeded);
+                }
+            }
+        }
+
+        self[address] = value;
+        Ok(())
+    }
+}
+
+impl<C: Cell> Index<usize> for Memory<C> {
+    type Output = C;
+
+    #[inline]
+    fn index(&self, index: usize) -> &Self::Output {
+        if index < self.starting_memory.len() {
+            &self.starting_memory[index]
+        } else {
+            match self.page(index / PAGE_SIZE) {
+                Some(page) => &page[index % PAGE_SIZE],
+                None => &self.zero,
+            }
+        }
+    }
+}
+
+impl<C: Cell> IndexMut<usize> for Memory<C> {
+    #[inline]
+    fn index_mut(&mut self, index: usize) -> &mut Self::Output {
+        if index < self.starting_memory.len() {
+            &mut self.starting_memory[index]
+        } else {
+            &mut self.page_mut(index / PAGE_SIZE)[index % PAGE_SIZE]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sparse() {
+        let mut memory = Memory::<i64>::new(vec![1, 2, 3]);
+        memory.write(1_000_000_000, 5).unwrap();
+        memory.write(1_000_000_001, 6).unwrap();
+        memory.write(7, 0).unwrap();
+
+        assert_eq!(memory.extra_cells(), 2 * PAGE_SIZE);
+        assert_eq!(memory[1_000_000_000], 5);
+        assert_eq!(memory.read(2_000_000_000), Ok(&0));
+
+        let mut extra = memory.extra().collect::<Vec<_>>();
+        extra.sort();
+        assert_eq!(extra, vec![(1_000_000_000, 5), (1_000_000_001, 6)]);
+    }
+
+    #[test]
+    fn limits() {
+        let mut memory = Memory::<i64>::new(vec![1, 2, 3]);
+        memory.limits = MemoryLimits {
+            max_address: Some(10_000),
+            max_extra_cells: Some(PAGE_SIZE),
+        };
+
+        assert_eq!(memory.write(10, 1), Ok(()));
+        assert_eq!(memory.write(20, 1), Ok(()));
+        assert_eq!(memory.write(5000, 1), Err(MemoryFault::BudgetExceeded));
+        assert_eq!(memory.write(20_000, 1), Err(MemoryFault::OutOfRange));
+        assert_eq!(memory.read(20_000), Err(MemoryFault::OutOfRange));
+
+        // Budgets that aren't whole pages still allow the page they start.
+        let mut memory = Memory::<i64>::new(vec![1, 2, 3]);
+        memory.limits = MemoryLimits::default().with_max_extra_cells(10);
+        assert_eq!(memory.write(3, 1), Ok(()));
+        assert_eq!(
+            memory.write(PAGE_SIZE + 1, 1),
+            Err(MemoryFault::BudgetExceeded)
+        );
+
+        memory.limits = MemoryLimits::default().with_max_extra_cells(0);
+        assert_eq!(memory.write(2, 1), Ok(()));
+        assert_eq!(
+            memory.write(PAGE_SIZE + 1, 1),
+            Err(MemoryFault::BudgetExceeded)
+        );
+    }
+}
@@ -0,0 +1,1100 @@
+//! An IntCode virtual machine.
+//!
+//! Machines are built from a program with [`IntCodeBuilder`] or parsed straight from
+//! the comma separated puzzle format, then either run to completion or resumed one
+//! input or output at a time.
+//!
+//! ```
+//! use aoc2019::intcode::{IntCode, StepResult};
+//!
+//! let mut intcode = "3,9,1001,9,1,9,4,9,99,0".parse::<IntCode>().unwrap();
+//! assert_eq!(intcode.resume(), Ok(StepResult::NeedsInput));
+//! intcode.provide_input(41);
+//! assert_eq!(intcode.resume(), Ok(StepResult::Output(42)));
+//! assert_eq!(intcode.resume(), Ok(StepResult::Halted));
+//! ```
+
+#![warn(missing_docs)]
+
+pub mod ascii;
+pub mod assembler;
+pub mod asynchronous;
+pub mod audit;
+pub mod builder;
+pub mod cell;
+pub mod cfg;
+pub mod compiled;
+#[doc(hidden)]
+pub mod debugger;
+pub mod disassembler;
+#[doc(hidden)]
+pub mod fuzz;
+pub mod history;
+pub mod interrupt;
+pub mod memo;
+pub mod memory;
+pub mod network;
+pub mod outputs;
+#[doc(hidden)]
+pub mod play;
+pub mod search;
+pub mod session;
+pub mod snapshot;
+pub mod symbolic;
+pub mod trace;
+
+pub use builder::IntCodeBuilder;
+use cell::*;
+use compiled::*;
+use crossbeam::channel::*;
+use interrupt::*;
+use memory::*;
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::fmt;
+use std::time::Duration;
+use trace::*;
+use Mode::*;
+use Opcode::*;
+
+/// The cell type machines use unless told otherwise.
+pub type IntCodeCell = i64;
+
+const CLOCK_POLL_INTERVAL: u32 = 1024;
+const CHANNEL_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// A single machine: its memory, registers and queued input.
+///
+/// Cells are `i64` unless another [`Cell`] type is chosen.
+#[derive(Clone)]
+pub struct IntCode<C: Cell = IntCodeCell> {
+    memory: Memory<C>,
+    pc: usize,
+    relative_base: C,
+    inputs: VecDeque<C>,
+    limits: RunLimits,
+    code: Code<C>,
+}
+
+/// Why a machine stopped running.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum StepResult<C = IntCodeCell> {
+    /// The program is waiting at an input instruction with nothing queued.
+    NeedsInput,
+    /// The program wrote this value.
+    Output(C),
+    /// The program ran its halt instruction.
+    Halted,
+    /// A run limit stopped the program before its next instruction.
+    Interrupted(Interrupt),
+}
+
+/// A fault in the running program.
+///
+/// Every variant has the `pc` of the instruction that failed and the raw
+/// `instruction` value found there, as returned by [`pc`](Self::pc) and
+/// [`instruction`](Self::instruction).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+#[allow(missing_docs)]
+pub enum IntCodeError {
+    /// The opcode isn't one IntCode defines.
+    BadOpcode { pc: usize, instruction: IntCodeCell },
+    /// A parameter mode other than position, immediate or relative.
+    BadMode { pc: usize, instruction: IntCodeCell },
+    /// The parameter written to is in immediate mode.
+    ImmediateWrite { pc: usize, instruction: IntCodeCell },
+    /// A parameter points below address zero.
+    NegativeAddress {
+        pc: usize,
+        instruction: IntCodeCell,
+        /// The address it tried to use.
+        address: IntCodeCell,
+    },
+    /// A parameter points past the `max_address` memory limit, or past any address
+    /// memory could have.
+    AddressOutOfRange {
+        pc: usize,
+        instruction: IntCodeCell,
+        /// The address it tried to use. Values too big for an `IntCodeCell`
+        /// saturate.
+        address: IntCodeCell,
+    },
+    /// A write past the end of the program would allocate more than the
+    /// `max_extra_cells` memory limit allows.
+    MemoryBudgetExceeded {
+        pc: usize,
+        instruction: IntCodeCell,
+        /// The address written to.
+        address: usize,
+    },
+    /// The program read when no more input was coming.
+    InputExhausted { pc: usize, instruction: IntCodeCell },
+    /// The program read from or wrote to a channel whose other end is gone.
+    ChannelClosed { pc: usize, instruction: IntCodeCell },
+    /// A run limit stopped a run that had to finish.
+    Interrupted {
+        pc: usize,
+        instruction: IntCodeCell,
+        /// Which limit it was.
+        interrupt: Interrupt,
+    },
+    /// Arithmetic or the relative base left the range of the cell type.
+    Overflow { pc: usize, instruction: IntCodeCell },
+}
+
+impl IntCodeError {
+    /// The address of the instruction that failed.
+    pub fn pc(&self) -> usize {
+        match *self {
+            IntCodeError::BadOpcode { pc, .. }
+            | IntCodeError::BadMode { pc, .. }
+            | IntCodeError::ImmediateWrite { pc, .. }
+            | IntCodeError::NegativeAddress { pc, .. }
+            | IntCodeError::AddressOutOfRange { pc, .. }
+            | IntCodeError::MemoryBudgetExceeded { pc, .. }
+            | IntCodeError::InputExhausted { pc, .. }
+            | IntCodeError::ChannelClosed { pc, .. }
+            | IntCodeError::Interrupted { pc, .. }
+            | IntCodeError::Overflow { pc, .. } => pc,
+        }
+    }
+
+    /// The failing instruction as stored, mode digits included. Values too big for
+    /// an `IntCodeCell` saturate.
+    pub fn instruction(&self) -> IntCodeCell {
+        match *self {
+            IntCodeError::BadOpcode { instruction, .. }
+            | IntCodeError::BadMode { instruction, .. }
+            | IntCodeError::ImmediateWrite { instruction, .. }
+            | IntCodeError::NegativeAddress { instruction, .. }
+            | IntCodeError::AddressOutOfRange { instruction, .. }
+            | IntCodeError::MemoryBudgetExceeded { instruction, .. }
+            | IntCodeError::InputExhausted { instruction, .. }
+            | IntCodeError::ChannelClosed { instruction, .. }
+            | IntCodeError::Interrupted { instruction, .. }
+            | IntCodeError::Overflow { instruction, .. } => instruction,
+        }
+    }
+}
+
+impl fmt::Display for IntCodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            IntCodeError::BadOpcode { .. } => write!(f, "bad opcode")?,
+            IntCodeError::BadMode { .. } => write!(f, "bad parameter mode")?,
+            IntCodeError::ImmediateWrite { .. } => write!(f, "write in immediate mode")?,
+            IntCodeError::NegativeAddress { address, .. } => {
+                write!(f, "negative address {}", address)?
+            }
+            IntCodeError::AddressOutOfRange { address, .. } => {
+                write!(f, "address {} out of range", address)?
+            }
+            IntCodeError::MemoryBudgetExceeded { address, .. } => {
+                write!(f, "memory budget exceeded writing address {}", address)?
+            }
+            IntCodeError::InputExhausted { .. } => write!(f, "ran out of input")?,
+            IntCodeError::ChannelClosed { .. } => write!(f, "channel closed")?,
+            IntCodeError::Interrupted { interrupt, .. } => {
+                write!(f, "interrupted ({:?})", interrupt)?
+            }
+            IntCodeError::Overflow { .. } => write!(f, "arithmetic overflow")?,
+        }
+
+        write!(
+            f,
+            " at pc {} (instruction {})",
+            self.pc(),
+            self.instruction()
+        )
+    }
+}
+
+impl std::error::Error for IntCodeError {}
+
+impl<C: Cell> std::str::FromStr for IntCode<C> {
+    type Err = C::Err;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(s.parse::<IntCodeBuilder<C>>()?.build())
+    }
+}
+
+impl<C: Cell> fmt::Display for IntCode<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (index, value) in self.program().iter().enumerate() {
+            if index > 0 {
+                write!(f, ",")?;
+            }
+            write!(f, "{}", value)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<C: Cell> IntCode<C> {
+    fn new(program: Vec<C>) -> Self {
+        Self {
+            memory: Memory::new(program),
+            pc: 0,
+            relative_base: C::default(),
+            inputs: VecDeque::new(),
+            limits: RunLimits::default(),
+            code: Code::new(Engine::default()),
+        }
+    }
+
+    /// Writes one cell, as the program itself could.
+    pub fn replace_cell(&mut self, index: usize, value: C) {
+        self.memory[index] = value;
+        self.code.invalidate(index);
+    }
+
+    /// Any address can be read; cells nothing has written to yet are zero.
+    pub fn cell(&self, index: usize) -> C {
+        self.memory[index].clone()
+    }
+
+    /// The cells the program was loaded into, as they are now.
+    pub fn program(&self) -> &[C] {
+        &self.memory.starting_memory
+    }
+
+    /// The address of the next instruction to run.
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    /// Moves execution to `pc`, as a jump would.
+    pub fn set_pc(&mut self, pc: usize) {
+        self.pc = pc;
+    }
+
+    /// What relative mode parameters are offset from.
+    pub fn relative_base(&self) -> C {
+        self.relative_base.clone()
+    }
+
+    /// Sets the relative base outright, rather than adjusting it as `arb` does.
+    pub fn set_relative_base(&mut self, relative_base: C) {
+        self.relative_base = relative_base;
+    }
+
+    /// The limits on memory the program can use.
+    pub fn memory_limits(&self) -> MemoryLimits {
+        self.memory.limits
+    }
+
+    /// Applies from the next access on; memory already allocated is kept.
+    pub fn set_memory_limits(&mut self, limits: MemoryLimits) {
+        self.memory.limits = limits;
+    }
+
+    /// Cells allocated past the end of the program.
+    pub fn extra_memory_cells(&self) -> usize {
+        self.memory.extra_cells()
+    }
+
+    /// The limits on how long the machine may run, with whatever is left of the
+    /// instruction budget.
+    pub fn run_limits(&self) -> &RunLimits {
+        &self.limits
+    }
+
+    /// Replaces the run limits, budget included.
+    pub fn set_run_limits(&mut self, limits: RunLimits) {
+        self.limits = limits;
+    }
+
+    /// Input queued but not read yet, oldest first.
+    pub fn pending_inputs(&self) -> impl Iterator<Item = C> + '_ {
+        self.inputs.iter().cloned()
+    }
+
+    /// Runs a program that never reads or writes to completion, returning its final
+    /// program memory.
+    pub fn run_no_io(mut self) -> Result<Vec<C>, IntCodeError> {
+        self.run_with_fns(|| None, |_| ())?;
+        Ok(self.memory.starting_memory)
+    }
+
+    /// Runs to completion on the given input, returning everything it output.
+    pub fn run_with_input(mut self, input: &[C]) -> Result<Vec<C>, IntCodeError> {
+        let mut inputs = input.iter();
+        let mut outputs = Vec::new();
+
+        self.run_with_fns(|| inputs.next().cloned(), |o| outputs.push(o))?;
+        Ok(outputs)
+    }
+
+    /// Runs to completion, calling `input` whenever the program reads. Returning `None`
+    /// fails the run with `InputExhausted`.
+    pub fn run_with_fns(
+        &mut self,
+        mut input: impl FnMut() -> Option<C>,
+        mut output: impl FnMut(C),
+    ) -> Result<(), IntCodeError> {
+        loop {
+            match self.resume()? {
+                StepResult::NeedsInput => match input() {
+                    Some(value) => self.provide_input(value),
+                    None => {
+                        return Err(IntCodeError::InputExhausted {
+                            pc: self.pc,
+                            instruction: self.memory[self.pc].saturating_i64(),
+                        })
+                    }
+                },
+                StepResult::Output(value) => output(value),
+                StepResult::Halted => return Ok(()),
+                StepResult::Interrupted(interrupt) => return Err(self.interrupted(interrupt)),
+            }
+        }
+    }
+
+    /// Runs to completion, blocking on `input` whenever the program reads.
+    pub fn run_with_channels(
+        mut self,
+        input: Receiver<C>,
+        output: Sender<C>,
+    ) -> Result<(), IntCodeError> {
+        loop {
+            match self.resume()? {
+                StepResult::NeedsInput => {
+                    let value = if self.limits.is_unlimited() {
+                        input.recv().map_err(|_| self.channel_closed(self.pc))?
+                    } else {
+                        self.recv_polling(&input)?
+                    };
+                    self.provide_input(value);
+                }
+                StepResult::Output(value) => {
+                    if output.send(value).is_err() {
+                        // The output instruction has already been stepped over.
+                        return Err(self.channel_closed(self.pc - 2));
+                    }
+                }
+                StepResult::Halted => return Ok(()),
+                StepResult::Interrupted(interrupt) => return Err(self.interrupted(interrupt)),
+            }
+        }
+    }
+
+    fn recv_polling(&self, input: &Receiver<C>) -> Result<C, IntCodeError> {
+        loop {
+            match input.recv_timeout(CHANNEL_POLL_INTERVAL) {
+                Ok(value) => return Ok(value),
+                Err(RecvTimeoutError::Timeout) => {
+                    if let Some(interrupt) = self.limits.check_clock() {
+                        return Err(self.interrupted(interrupt));
+                    }
+                }
+                Err(RecvTimeoutError::Disconnected) => return Err(self.channel_closed(self.pc)),
+            }
+        }
+    }
+
+    /// Queues a value for the program to read.
+    pub fn provide_input(&mut self, value: C) {
+        self.inputs.push_back(value);
+    }
+
+    /// Runs until the program outputs, needs input it doesn't have, halts, or hits
+    /// one of its run limits.
+    pub fn resume(&mut self) -> Result<StepResult<C>, IntCodeError> {
+        self.resume_traced(&mut ())
+    }
+
+    /// Like `resume`, but reports every instruction to `tracer`. Traced machines always
+    /// use the interpreter.
+    pub fn resume_traced(
+        &mut self,
+        tracer: &mut impl Tracer<C>,
+    ) -> Result<StepResult<C>, IntCodeError> {
+        if self.limits.is_unlimited() {
+            loop {
+                if let Some(result) = self.step_traced(tracer)? {
+                    return Ok(result);
+                }
+            }
+        }
+
+        let mut until_poll = 0;
+        loop {
+            if until_poll == 0 {
+                if let Some(interrupt) = self.limits.check_clock() {
+                    return Ok(StepResult::Interrupted(interrupt));
+                }
+                until_poll = CLOCK_POLL_INTERVAL;
+            }
+            until_poll -= 1;
+
+            if let Some(interrupt) = self.limits.check_budget() {
+                return Ok(StepResult::Interrupted(interrupt));
+            }
+
+            match self.step_traced(tracer)? {
+                Some(StepResult::NeedsInput) => return Ok(StepResult::NeedsInput),
+                result => {
+                    self.limits.spend();
+                    if let Some(result) = result {
+                        return Ok(result);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Runs a single instruction, returning `None` if it's one that doesn't stop the
+    /// machine. Ignores run limits.
+    pub fn step(&mut self) -> Result<Option<StepResult<C>>, IntCodeError> {
+        self.step_traced(&mut ())
+    }
+
+    /// Like [`step`](Self::step), reporting the instruction to `tracer`. Tracing
+    /// always runs on the interpreter.
+    #[inline]
+    pub fn step_traced<T: Tracer<C>>(
+        &mut self,
+        tracer: &mut T,
+    ) -> Result<Option<StepResult<C>>, IntCodeError> {
+        if !T::ENABLED && self.code.engine == Engine::Compiled {
+            return self.step_compiled();
+        }
+        self.interpret(tracer)
+    }
+
+    fn interpret<T: Tracer<C>>(
+        &mut self,
+        tracer: &mut T,
+    ) -> Result<Option<StepResult<C>>, IntCodeError> {
+        let instr = Instruction::decode(self.pc, &self.memory[self.pc])?;
+        // Untraced runs don't build an event at all.
+        let mut event = if T::ENABLED {
+            Some(TraceEvent::new(instr))
+        } else {
+            None
+        };
+
+        let result = match instr.opcode {
+            Add | Multiply | LessThan | Equals => {
+                self.do_math(instr, &mut event)?;
+                None
+            }
+            JumpIfTrue | JumpIfFalse => {
+                self.do_jump(instr, &mut event)?;
+                None
+            }
+
+            Input => match self.inputs.front() {
+                Some(value) => {
+                    self.set_parameter(1, instr, value.clone(), &mut event)?;
+                    self.inputs.pop_front();
+                    self.pc += 2;
+                    None
+                }
+                None => return Ok(Some(StepResult::NeedsInput)),
+            },
+
+            Output => {
+                let value = self.get_parameter(1, instr, &mut event)?;
+                self.pc += 2;
+                Some(StepResult::Output(value))
+            }
+
+            AdjustRelativeBase => {
+                let offset = self.get_parameter(1, instr, &mut event)?;
+                self.relative_base = instr.checked(self.relative_base.checked_add(&offset))?;
+                self.pc += 2;
+                None
+            }
+
+            Terminate => Some(StepResult::Halted),
+        };
+
+        if let Some(event) = &event {
+            tracer.trace(event);
+        }
+
+        Ok(result)
+    }
+
+    fn channel_closed(&self, pc: usize) -> IntCodeError {
+        IntCodeError::ChannelClosed {
+            pc,
+            instruction: self.memory[pc].saturating_i64(),
+        }
+    }
+
+    fn interrupted(&self, interrupt: Interrupt) -> IntCodeError {
+        IntCodeError::Interrupted {
+            pc: self.pc,
+            instruction: self.memory[self.pc].saturating_i64(),
+            interrupt,
+        }
+    }
+
+    fn do_math(
+        &mut self,
+        instr: Instruction,
+        event: &mut Option<TraceEvent<C>>,
+    ) -> Result<(), IntCodeError> {
+        let value1 = self.get_parameter(1, instr, event)?;
+        let value2 = self.get_parameter(2, instr, event)?;
+
+        let result = match instr.opcode {
+            Add => instr.checked(value1.checked_add(&value2))?,
+            Multiply => instr.checked(value1.checked_mul(&value2))?,
+            LessThan => C::from_i64((value1 < value2).into()),
+            Equals => C::from_i64((value1 == value2).into()),
+            _ => unreachable!(),
+        };
+
+        self.set_parameter(3, instr, result, event)?;
+        self.pc += 4;
+        Ok(())
+    }
+
+    fn do_jump(
+        &mut self,
+        instr: Instruction,
+        event: &mut Option<TraceEvent<C>>,
+    ) -> Result<(), IntCodeError> {
+        let cond = self.get_parameter(1, instr, event)?;
+        let new_pc = self.get_parameter(2, instr, event)?;
+
+        if match instr.opcode {
+            JumpIfTrue => !cond.is_zero(),
+            JumpIfFalse => cond.is_zero(),
+            _ => unreachable!(),
+        } {
+            self.pc = instr.address(&new_pc)?;
+        } else {
+            self.pc += 3;
+        }
+        Ok(())
+    }
+
+    fn get_parameter(
+        &self,
+        offset: usize,
+        instr: Instruction,
+        event: &mut Option<TraceEvent<C>>,
+    ) -> Result<C, IntCodeError> {
+        let index = self.pc + offset;
+
+        let value = match instr.modes[offset - 1] {
+            Position => self.read(instr, instr.address(&self.memory[index])?)?,
+            Immediate => self.memory[index].clone(),
+            Relative => self.read(instr, self.relative_address(instr, index)?)?,
+        };
+
+        if let Some(event) = event {
+            event.operands[offset - 1] = Some(value.clone());
+        }
+        Ok(value)
+    }
+
+    fn set_parameter(
+        &mut self,
+        offset: usize,
+        instr: Instruction,
+        value: C,
+        event: &mut Option<TraceEvent<C>>,
+    ) -> Result<(), IntCodeError> {
+        let index = self.pc + offset;
+
+        let address = match instr.modes[offset - 1] {
+            Position => instr.address(&self.memory[index])?,
+            Immediate => {
+                return Err(IntCodeError::ImmediateWrite {
+                    pc: instr.pc,
+                    instruction: instr.raw,
+                })
+            }
+            Relative => self.relative_address(instr, index)?,
+        };
+
+        match event {
+            Some(event) => {
+                let old = self.memory[address].clone();
+                self.write(instr, address, value.clone())?;
+                event.write = Some(MemoryWrite {
+                    address,
+                    old,
+                    new: value,
+                });
+            }
+            None => self.write(instr, address, value)?,
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, instr: Instruction, address: usize, value: C) -> Result<(), IntCodeError> {
+        self.memory
+            .write(address, value)
+            .map_err(|fault| instr.memory_fault(fault, address))?;
+        self.code.invalidate(address);
+        Ok(())
+    }
+
+    fn relative_address(&self, instr: Instruction, index: usize) -> Result<usize, IntCodeError> {
+        let address = instr.checked(self.memory[index].checked_add(&self.relative_base))?;
+        instr.address(&address)
+    }
+
+    fn read(&self, instr: Instruction, address: usize) -> Result<C, IntCodeError> {
+        self.memory
+            .read(address)
+            .cloned()
+            .map_err(|fault| instr.memory_fault(fault, address))
+    }
+}
+
+#[derive(Copy, Clone)]
+struct Instruction {
+    pc: usize,
+    raw: IntCodeCell,
+    opcode: Opcode,
+    modes: [Mode; 3],
+}
+
+impl Instruction {
+    fn new(pc: usize, raw: IntCodeCell) -> Result<Self, IntCodeError> {
+        let opcode = Opcode::new(raw % 100).ok_or(IntCodeError::BadOpcode {
+            pc,
+            instruction: raw,
+        })?;
+        let mode = |val| {
+            Mode::new(val % 10).ok_or(IntCodeError::BadMode {
+                pc,
+                instruction: raw,
+            })
+        };
+
+        Ok(Self {
+            pc,
+            raw,
+            opcode,
+            modes: [mode(raw / 100)?, mode(raw / 1000)?, mode(raw / 10000)?],
+        })
+    }
+
+    fn len(&self) -> usize {
+        self.opcode.parameters() + 1
+    }
+
+    fn is_canonical(&self) -> bool {
+        self.raw == Self::encode(self.opcode, &self.modes[..self.opcode.parameters()])
+    }
+
+    fn encode(opcode: Opcode, modes: &[Mode]) -> IntCodeCell {
+        modes
+            .iter()
+            .rev()
+            .fold(0, |acc, mode| acc * 10 + mode.value())
+            * 100
+            + opcode.value()
+    }
+
+    fn memory_fault(&self, fault: MemoryFault, address: usize) -> IntCodeError {
+        match fault {
+            MemoryFault::OutOfRange => IntCodeError::AddressOutOfRange {
+                pc: self.pc,
+                instruction: self.raw,
+                address: address as IntCodeCell,
+            },
+            MemoryFault::BudgetExceeded => IntCodeError::MemoryBudgetExceeded {
+                pc: self.pc,
+                instruction: self.raw,
+                address,
+            },
+        }
+    }
+
+    fn decode<C: Cell>(pc: usize, raw: &C) -> Result<Self, IntCodeError> {
+        match raw.to_i64() {
+            Some(raw) => Self::new(pc, raw),
+            None => Err(IntCodeError::BadOpcode {
+                pc,
+                instruction: raw.saturating_i64(),
+            }),
+        }
+    }
+
+    fn address<C: Cell>(&self, address: &C) -> Result<usize, IntCodeError> {
+        match address.to_i64() {
+            Some(address) if address >= 0 => {
+                usize::try_from(address).map_err(|_| IntCodeError::AddressOutOfRange {
+                    pc: self.pc,
+                    instruction: self.raw,
+                    address,
+                })
+            }
+            _ if *address >= C::default() => Err(IntCodeError::AddressOutOfRange {
+                pc: self.pc,
+                instruction: self.raw,
+                address: address.saturating_i64(),
+            }),
+            _ => Err(IntCodeError::NegativeAddress {
+                pc: self.pc,
+                instruction: self.raw,
+                address: address.saturating_i64(),
+            }),
+        }
+    }
+
+    fn checked<C>(&self, value: Option<C>) -> Result<C, IntCodeError> {
+        value.ok_or(IntCodeError::Overflow {
+            pc: self.pc,
+            instruction: self.raw,
+        })
+    }
+}
+
+/// The operation an instruction performs.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Opcode {
+    /// Opcode 1: `c = a + b`.
+    Add,
+    /// Opcode 2: `c = a * b`.
+    Multiply,
+    /// Opcode 3: reads input into `a`.
+    Input,
+    /// Opcode 4: outputs `a`.
+    Output,
+    /// Opcode 5: jumps to `b` if `a` isn't zero.
+    JumpIfTrue,
+    /// Opcode 6: jumps to `b` if `a` is zero.
+    JumpIfFalse,
+    /// Opcode 7: `c = a < b`, as 1 or 0.
+    LessThan,
+    /// Opcode 8: `c = a == b`, as 1 or 0.
+    Equals,
+    /// Opcode 9: adds `a` to the relative base.
+    AdjustRelativeBase,
+    /// Opcode 99: halts.
+    Terminate,
+}
+
+impl Opcode {
+    fn new(val: IntCodeCell) -> Option<Self> {
+        Some(match val {
+            1 => Add,
+            2 => Multiply,
+            3 => Input,
+            4 => Output,
+            5 => JumpIfTrue,
+            6 => JumpIfFalse,
+            7 => LessThan,
+            8 => Equals,
+            9 => AdjustRelativeBase,
+            99 => Terminate,
+            _ => return None,
+        })
+    }
+
+    fn value(self) -> IntCodeCell {
+        match self {
+            Add => 1,
+            Multiply => 2,
+            Input => 3,
+            Output => 4,
+            JumpIfTrue => 5,
+            JumpIfFalse => 6,
+            LessThan => 7,
+            Equals => 8,
+            AdjustRelativeBase => 9,
+            Terminate => 99,
+        }
+    }
+
+    /// How many parameters follow the opcode.
+    pub fn parameters(self) -> usize {
+        match self {
+            Add | Multiply | LessThan | Equals => 3,
+            JumpIfTrue | JumpIfFalse => 2,
+            Input | Output | AdjustRelativeBase => 1,
+            Terminate => 0,
+        }
+    }
+
+    /// The name the assembler and disassembler use.
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            Add => "add",
+            Multiply => "mul",
+            Input => "in",
+            Output => "out",
+            JumpIfTrue => "jt",
+            JumpIfFalse => "jf",
+            LessThan => "lt",
+            Equals => "eq",
+            AdjustRelativeBase => "arb",
+            Terminate => "hlt",
+        }
+    }
+}
+
+/// How a parameter is turned into a value or an address.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Mode {
+    /// Mode 0: the parameter is an address.
+    Position,
+    /// Mode 1: the parameter is the value itself.
+    Immediate,
+    /// Mode 2: the parameter is an address relative to the relative base.
+    Relative,
+}
+
+impl Mode {
+    fn new(val: IntCodeCell) -> Option<Self> {
+        Some(match val {
+            0 => Position,
+            1 => Immediate,
+            2 => Relative,
+            _ => return None,
+        })
+    }
+
+    fn value(self) -> IntCodeCell {
+        match self {
+            Position => 0,
+            Immediate => 1,
+            Relative => 2,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(program: &str, inputs: &[IntCodeCell]) -> Result<Vec<IntCodeCell>, IntCodeError> {
+        let intcode = program.parse::<IntCode>().unwrap();
+        let mut compiled = intcode.clone();
+        compiled.set_engine(Engine::Compiled);
+
+        let result = intcode.run_with_input(inputs);
+        assert_eq!(compiled.run_with_input(inputs), result);
+        result
+    }
+
+    #[test]
+    fn resume() {
+        // Outputs the sum and then the product of two inputs.
+        let program = IntCode::assemble(
+            "
+                in    [a]
+                in    [b]
+                add   [a], [b], [c]
+                out   [c]
+                mul   [a], [b], [c]
+                out   [c]
+                hlt
+            a: .data 0
+            b: .data 0
+            c: .data 0
+            ",
+        )
+        .unwrap();
+
+        for &engine in &[Engine::Interpreter, Engine::Compiled] {
+            let mut intcode = program.clone();
+            intcode.set_engine(engine);
+
+            assert_eq!(intcode.resume(), Ok(StepResult::NeedsInput));
+            assert_eq!(intcode.pc(), 0);
+            // Still waiting, without having run anything.
+            assert_eq!(intcode.resume(), Ok(StepResult::NeedsInput));
+            assert_eq!(intcode.pc(), 0);
+
+            intcode.provide_input(3);
+            assert_eq!(intcode.resume(), Ok(StepResult::NeedsInput));
+            assert_eq!(intcode.pc(), 2);
+
+            intcode.provide_input(4);
+            assert_eq!(intcode.resume(), Ok(StepResult::Output(7)));
+            assert_eq!(intcode.resume(), Ok(StepResult::Output(12)));
+            assert_eq!(intcode.resume(), Ok(StepResult::Halted));
+            assert_eq!(intcode.resume(), Ok(StepResult::Halted));
+
+            // Input queued ahead of time is read without stopping.
+            let mut intcode = program.clone();
+            intcode.set_engine(engine);
+            intcode.provide_input(5);
+            intcode.provide_input(6);
+            assert_eq!(intcode.pending_inputs().collect::<Vec<_>>(), vec![5, 6]);
+            assert_eq!(intcode.resume(), Ok(StepResult::Output(11)));
+            assert_eq!(intcode.pending_inputs().count(), 0);
+        }
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            run("1,0,0,0,42", &[]),
+            Err(IntCodeError::BadOpcode {
+                pc: 4,
+                instruction: 42
+            })
+        );
+        assert_eq!(
+            run("304,0,99", &[]),
+            Err(IntCodeError::BadMode {
+                pc: 0,
+                instruction: 304
+            })
+        );
+        assert_eq!(
+            run("103,0,99", &[1]),
+            Err(IntCodeError::ImmediateWrite {
+                pc: 0,
+                instruction: 103
+            })
+        );
+        assert_eq!(
+            run("4,-3,99", &[]),
+            Err(IntCodeError::NegativeAddress {
+                pc: 0,
+                instruction: 4,
+                address: -3
+            })
+        );
+        assert_eq!(
+            run("3,0,3,0,99", &[1]),
+            Err(IntCodeError::InputExhausted {
+                pc: 2,
+                instruction: 3
+            })
+        );
+    }
+
+    #[test]
+    fn memory_limits() {
+        let mut intcode = "1101,1,2,1000000000,99".parse::<IntCode>().unwrap();
+        intcode.set_memory_limits(MemoryLimits {
+            max_address: Some(1_000_000),
+            max_extra_cells: None,
+        });
+        assert_eq!(
+            intcode.clone().run_no_io(),
+            Err(IntCodeError::AddressOutOfRange {
+                pc: 0,
+                instruction: 1101,
+                address: 1_000_000_000
+            })
+        );
+
+        intcode.set_memory_limits(MemoryLimits {
+            max_address: None,
+            max_extra_cells: Some(0),
+        });
+        assert_eq!(
+            intcode.clone().run_no_io(),
+            Err(IntCodeError::MemoryBudgetExceeded {
+                pc: 0,
+                instruction: 1101,
+                address: 1_000_000_000
+            })
+        );
+
+        intcode.set_memory_limits(MemoryLimits::default());
+        assert!(intcode.run_no_io().is_ok());
+    }
+
+    #[test]
+    fn run_limits() {
+        let mut intcode = IntCode::assemble(
+            "
+                in    [count]
+            loop:
+                add   [count], #-1, [count]
+                jt    [count], #loop
+                out   #42
+                hlt
+            count: .data 0
+            ",
+        )
+        .unwrap();
+        intcode.provide_input(100);
+
+        intcode.set_run_limits(RunLimits {
+            instruction_budget: Some(51),
+            ..RunLimits::default()
+        });
+        assert_eq!(
+            intcode.resume(),
+            Ok(StepResult::Interrupted(Interrupt::BudgetExhausted))
+        );
+        assert_eq!((intcode.pc(), intcode.cell(12)), (2, 75));
+
+        intcode.set_run_limits(RunLimits {
+            deadline: Some(std::time::Instant::now()),
+            ..RunLimits::default()
+        });
+        assert_eq!(
+            intcode.resume(),
+            Ok(StepResult::Interrupted(Interrupt::DeadlineExceeded))
+        );
+
+        let token = CancellationToken::new();
+        intcode.set_run_limits(RunLimits {
+            cancellation: Some(token.clone()),
+            ..RunLimits::default()
+        });
+        token.cancel();
+        assert_eq!(
+            intcode.resume(),
+            Ok(StepResult::Interrupted(Interrupt::Cancelled))
+        );
+
+        intcode.set_run_limits(RunLimits::default());
+        assert_eq!(intcode.resume(), Ok(StepResult::Output(42)));
+    }
+
+    #[test]
+    fn cancel_blocked_channel() {
+        let (input_send, input_recv) = unbounded();
+        let (output_send, _) = unbounded();
+        let token = CancellationToken::new();
+
+        let mut intcode = "3,0,99".parse::<IntCode>().unwrap();
+        intcode.set_run_limits(RunLimits {
+            cancellation: Some(token.clone()),
+            ..RunLimits::default()
+        });
+
+        let handle = std::thread::spawn(move || intcode.run_with_channels(input_recv, output_send));
+        token.cancel();
+        assert_eq!(
+            handle.join().unwrap(),
+            Err(IntCodeError::Interrupted {
+                pc: 0,
+                instruction: 3,
+                interrupt: Interrupt::Cancelled
+            })
+        );
+        drop(input_send);
+    }
+
+    #[test]
+    fn closed_channel() {
+        let (input_send, input_recv) = unbounded();
+        let (output_send, _) = unbounded();
+        drop(input_send);
+
+        let intcode = "3,0,99".parse::<IntCode>().unwrap();
+        assert_eq!(
+            intcode.run_with_channels(input_recv, output_send),
+            Err(IntCodeError::ChannelClosed {
+                pc: 0,
+                instruction: 3
+            })
+        );
+    }
+}
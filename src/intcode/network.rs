@@ -0,0 +1,476 @@
+//! Networks of machines passing values or packets to each other.
+
+use super::*;
+use std::convert::TryFrom;
+
+/// A pair of values sent to one node of a packet switched network.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Packet {
+    /// The node it's addressed to.
+    pub destination: usize,
+    /// The first value, read before `y`.
+    pub x: IntCodeCell,
+    /// The second value.
+    pub y: IntCodeCell,
+}
+
+impl Packet {
+    /// A packet for `destination` holding `x` and `y`.
+    pub fn new(destination: usize, x: IntCodeCell, y: IntCodeCell) -> Self {
+        Self { destination, x, y }
+    }
+}
+
+/// Where the nodes of a network send their output.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Routing {
+    /// Every output is fed straight to the next node, wrapping around at the end.
+    Ring,
+    /// Outputs are grouped into (destination, x, y) packets.
+    Packets,
+}
+
+/// Why a network stopped running.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum NetworkStop<T> {
+    /// The monitor returned this.
+    Monitor(T),
+    /// Every node halted.
+    Halted,
+    /// Every node is waiting for input and nothing is on its way.
+    Idle,
+}
+
+/// A fault in one node of a network.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct NodeError {
+    /// The index of the node.
+    pub node: usize,
+    /// What went wrong there.
+    pub error: IntCodeError,
+}
+
+impl fmt::Display for NodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "node {}: {}", self.node, self.error)
+    }
+}
+
+impl std::error::Error for NodeError {}
+
+/// Watches a running network, and decides when to stop it.
+pub trait Monitor {
+    /// What the network's run returns when the monitor stops it.
+    type Output;
+
+    /// Called with packets addressed to a node that doesn't exist. Returning a value
+    /// stops the network.
+    fn packet(&mut self, network: &mut Network, packet: Packet) -> Option<Self::Output>;
+
+    /// Called with packets whose destination can't be a node's address at all, such
+    /// as a negative one. Returning a value stops the network; by default they're
+    /// dropped.
+    fn bad_destination(
+        &mut self,
+        _network: &mut Network,
+        _destination: IntCodeCell,
+        _x: IntCodeCell,
+        _y: IntCodeCell,
+    ) -> Option<Self::Output> {
+        None
+    }
+
+    /// Called when every node is waiting on an empty queue and nothing is in flight.
+    /// Returning a value stops the network; otherwise it keeps running if the
+    /// monitor sent anything.
+    fn idle(&mut self, network: &mut Network) -> Option<Self::Output>;
+}
+
+impl Monitor for () {
+    type Output = ();
+
+    fn packet(&mut self, _: &mut Network, _: Packet) -> Option<()> {
+        None
+    }
+
+    fn idle(&mut self, _: &mut Network) -> Option<()> {
+        None
+    }
+}
+
+// A packet no node will receive, for the monitor to see once the sender blocks.
+enum Undeliverable {
+    Packet(Packet),
+    BadDestination([IntCodeCell; 3]),
+}
+
+struct Node {
+    intcode: IntCode,
+    outgoing: Vec<IntCodeCell>,
+    halted: bool,
+}
+
+/// Machines running in turn, each feeding its output to the others.
+pub struct Network {
+    nodes: Vec<Node>,
+    routing: Routing,
+    default_input: Option<IntCodeCell>,
+}
+
+impl Network {
+    /// A network of `machines`, numbered in order, with no default input.
+    pub fn new(machines: Vec<IntCode>, routing: Routing) -> Self {
+        Self {
+            nodes: machines
+                .into_iter()
+                .map(|intcode| Node {
+                    intcode,
+                    outgoing: Vec::new(),
+                    halted: false,
+                })
+                .collect(),
+            routing,
+            default_input: None,
+        }
+    }
+
+    /// A ring of `machines`, each feeding the next.
+    pub fn ring(machines: Vec<IntCode>) -> Self {
+        Self::new(machines, Routing::Ring)
+    }
+
+    /// Boots `count` copies of a NIC, each told its address, reading -1 when idle.
+    pub fn packet_switched(nic: &IntCode, count: usize) -> Self {
+        let machines = (0..count)
+            .map(|address| {
+                let mut intcode = nic.clone();
+                intcode.provide_input(address as IntCodeCell);
+                intcode
+            })
+            .collect();
+
+        let mut network = Self::new(machines, Routing::Packets);
+        network.set_default_input(Some(-1));
+        network
+    }
+
+    /// What a node reads when its queue is empty, or `None` to have it wait. Each
+    /// node gets it once per turn.
+    pub fn set_default_input(&mut self, default_input: Option<IntCodeCell>) {
+        self.default_input = default_input;
+    }
+
+    /// The number of nodes.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Whether there are no nodes at all.
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// The machine at `index`.
+    pub fn node(&self, index: usize) -> &IntCode {
+        &self.nodes[index].intcode
+    }
+
+    /// The machine at `index`, to queue input or inspect its memory.
+    pub fn node_mut(&mut self, index: usize) -> &mut IntCode {
+        &mut self.nodes[index].intcode
+    }
+
+    /// Queues a packet's values as input to its destination, which must exist.
+    pub fn send(&mut self, packet: Packet) {
+        let intcode = &mut self.nodes[packet.destination].intcode;
+        intcode.provide_input(packet.x);
+        intcode.provide_input(packet.y);
+    }
+
+    /// Runs every node in turn until they all halt, the network goes idle or
+    /// `monitor` stops it.
+    pub fn run<M: Monitor>(
+        &mut self,
+        monitor: &mut M,
+    ) -> Result<NetworkStop<M::Output>, NodeError> {
+        let mut undeliverable = Vec::new();
+
+        loop {
+            let mut active = false;
+
+            for index in 0..self.nodes.len() {
+                active |= self.run_node(index, &mut undeliverable)?;
+
+                for packet in undeliverable.drain(..) {
+                    let output = match packet {
+                        Undeliverable::Packet(packet) => monitor.packet(self, packet),
+                        Undeliverable::BadDestination([destination, x, y]) => {
+                            monitor.bad_destination(self, destination, x, y)
+                        }
+                    };
+                    if let Some(output) = output {
+                        return Ok(NetworkStop::Monitor(output));
+                    }
+                }
+            }
+
+            if self.nodes.iter().all(|node| node.halted) {
+                return Ok(NetworkStop::Halted);
+            }
+
+            if !active && self.queues_empty() {
+                if let Some(output) = monitor.idle(self) {
+                    return Ok(NetworkStop::Monitor(output));
+                }
+                if self.queues_empty() {
+                    return Ok(NetworkStop::Idle);
+                }
+            }
+        }
+    }
+
+    fn queues_empty(&self) -> bool {
+        self.nodes
+            .iter()
+            .all(|node| node.halted || node.intcode.inputs.is_empty())
+    }
+
+    // Runs one node until it blocks, returning whether it did anything observable.
+    fn run_node(
+        &mut self,
+        index: usize,
+        undeliverable: &mut Vec<Undeliverable>,
+    ) -> Result<bool, NodeError> {
+        let error = |error| NodeError { node: index, error };
+        let mut active = false;
+        let mut defaulted = false;
+
+        loop {
+            let node = &mut self.nodes[index];
+            if node.halted {
+                return Ok(active);
+            }
+
+            match node.intcode.resume().map_err(error)? {
+                StepResult::Output(value) => {
+                    active = true;
+                    self.route(index, value, undeliverable);
+                }
+                StepResult::NeedsInput => match self.default_input {
+                    Some(value) if !defaulted => {
+                        defaulted = true;
+                        node.intcode.provide_input(value);
+                    }
+                    _ => return Ok(active),
+                },
+                StepResult::Halted => {
+                    node.halted = true;
+                    return Ok(true);
+                }
+                StepResult::Interrupted(interrupt) => {
+                    return Err(error(node.intcode.interrupted(interrupt)))
+                }
+            }
+        }
+    }
+
+    fn route(&mut self, from: usize, value: IntCodeCell, undeliverable: &mut Vec<Undeliverable>) {
+        match self.routing {
+            Routing::Ring => {
+                let to = (from + 1) % self.nodes.len();
+                self.nodes[to].intcode.provide_input(value);
+            }
+            Routing::Packets => {
+                let outgoing = &mut self.nodes[from].outgoing;
+                outgoing.push(value);
+                if let [destination, x, y] = outgoing[..] {
+                    outgoing.clear();
+                    match usize::try_from(destination) {
+                        Ok(destination) if destination < self.nodes.len() => {
+                            self.send(Packet::new(destination, x, y))
+                        }
+                        Ok(destination) => undeliverable.push(Undeliverable::Packet(Packet::new(
+                            destination,
+                            x,
+                            y,
+                        ))),
+                        Err(_) => {
+                            undeliverable.push(Undeliverable::BadDestination([destination, x, y]))
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Node 0 sends (10, 20) to node 1, and every node forwards what it receives
+    // to 255 with its own address added to x.
+    fn nic() -> IntCode {
+        IntCode::assemble(
+            "
+                in    [addr]
+                jt    [addr], #recv
+                out   #1
+                out   #10
+                out   #20
+            recv:
+                in    [x]
+                eq    [x], #-1, [tmp]
+                jt    [tmp], #recv
+                in    [y]
+                add   [x], [addr], [x]
+                out   #255
+                out   [x]
+                out   [y]
+                jt    #1, #recv
+            addr: .data 0
+            x:    .data 0
+            y:    .data 0
+            tmp:  .data 0
+            ",
+        )
+        .unwrap()
+    }
+
+    struct Nat {
+        last: Option<Packet>,
+        last_sent_y: Option<IntCodeCell>,
+    }
+
+    impl Monitor for Nat {
+        type Output = IntCodeCell;
+
+        fn packet(&mut self, _: &mut Network, packet: Packet) -> Option<IntCodeCell> {
+            self.last = Some(packet);
+            None
+        }
+
+        fn idle(&mut self, network: &mut Network) -> Option<IntCodeCell> {
+            let packet = self.last?;
+            if self.last_sent_y == Some(packet.y) {
+                return Some(packet.y);
+            }
+            self.last_sent_y = Some(packet.y);
+            network.send(Packet {
+                destination: 0,
+                ..packet
+            });
+            None
+        }
+    }
+
+    struct FirstPacket;
+
+    impl Monitor for FirstPacket {
+        type Output = Packet;
+
+        fn packet(&mut self, _: &mut Network, packet: Packet) -> Option<Packet> {
+            Some(packet)
+        }
+
+        fn idle(&mut self, _: &mut Network) -> Option<Packet> {
+            None
+        }
+    }
+
+    #[test]
+    fn packets() {
+        let mut network = Network::packet_switched(&nic(), 3);
+        assert_eq!(
+            network.run(&mut FirstPacket),
+            Ok(NetworkStop::Monitor(Packet {
+                destination: 255,
+                x: 11,
+                y: 20
+            }))
+        );
+
+        let mut network = Network::packet_switched(&nic(), 3);
+        let mut nat = Nat {
+            last: None,
+            last_sent_y: None,
+        };
+        assert_eq!(network.run(&mut nat), Ok(NetworkStop::Monitor(20)));
+        assert_eq!(nat.last.map(|packet| packet.x), Some(11));
+    }
+
+    #[test]
+    fn bad_destination() {
+        struct Misaddressed;
+
+        impl Monitor for Misaddressed {
+            type Output = (IntCodeCell, Packet);
+
+            fn packet(&mut self, _: &mut Network, _: Packet) -> Option<Self::Output> {
+                None
+            }
+
+            fn bad_destination(
+                &mut self,
+                _: &mut Network,
+                destination: IntCodeCell,
+                x: IntCodeCell,
+                y: IntCodeCell,
+            ) -> Option<Self::Output> {
+                Some((destination, Packet::new(0, x, y)))
+            }
+
+            fn idle(&mut self, _: &mut Network) -> Option<Self::Output> {
+                None
+            }
+        }
+
+        let sender = IntCode::assemble("out #-1\nout #5\nout #6\nhlt").unwrap();
+        let mut network = Network::new(vec![sender.clone()], Routing::Packets);
+        assert_eq!(
+            network.run(&mut Misaddressed),
+            Ok(NetworkStop::Monitor((-1, Packet::new(0, 5, 6))))
+        );
+
+        // Other monitors drop them.
+        let mut network = Network::new(vec![sender], Routing::Packets);
+        assert_eq!(network.run(&mut FirstPacket), Ok(NetworkStop::Halted));
+    }
+
+    #[test]
+    fn ring() {
+        // Doubles one value, then waits for another that never comes.
+        let doubler = "3,0,1002,0,2,0,4,0,3,0,99".parse::<IntCode>().unwrap();
+
+        let mut network = Network::ring(vec![doubler; 3]);
+        network.node_mut(0).provide_input(1);
+        assert_eq!(network.run(&mut ()), Ok(NetworkStop::Idle));
+        assert_eq!(network.node(0).cell(0), 8);
+        assert_eq!(network.node(0).pc(), 10);
+
+        let mut network = Network::ring(vec!["3,0,4,0,99".parse().unwrap(); 2]);
+        network.node_mut(0).provide_input(7);
+        assert_eq!(network.run(&mut ()), Ok(NetworkStop::Halted));
+        assert_eq!(
+            network.node(0).pending_inputs().collect::<Vec<_>>(),
+            vec![7]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let mut network = Network::ring(vec!["3,0,4,0,99".parse().unwrap(), "98".parse().unwrap()]);
+        network.node_mut(0).provide_input(7);
+        assert_eq!(
+            network.run(&mut ()),
+            Err(NodeError {
+                node: 1,
+                error: IntCodeError::BadOpcode {
+                    pc: 0,
+                    instruction: 98
+                }
+            })
+        );
+    }
+}
@@ -0,0 +1,189 @@
+//! Iterating over a machine's outputs.
+
+use super::*;
+
+/// A machine run lazily as an iterator over its outputs.
+///
+/// `input` is called whenever the program reads. Iteration ends when the program
+/// halts or fails, with `end` saying which; `input` returning `None` fails it with
+/// `InputExhausted`.
+///
+/// ```
+/// use aoc2019::intcode::IntCode;
+///
+/// let intcode = "104,1,104,2,104,3,104,4,99".parse::<IntCode>().unwrap();
+/// let mut pairs = intcode.outputs(|| None).tuples::<2>();
+/// assert_eq!(pairs.next(), Some([1, 2]));
+/// assert_eq!(pairs.next(), Some([3, 4]));
+/// assert_eq!(pairs.next(), None);
+/// assert_eq!(pairs.inner().end(), Some(Ok(())));
+/// ```
+pub struct Outputs<I, C: Cell = IntCodeCell> {
+    intcode: IntCode<C>,
+    input: I,
+    end: Option<Result<(), IntCodeError>>,
+}
+
+impl<I: FnMut() -> Option<C>, C: Cell> Outputs<I, C> {
+    /// Runs `intcode` from where it is, reading through `input`.
+    pub fn new(intcode: IntCode<C>, input: I) -> Self {
+        Self {
+            intcode,
+            input,
+            end: None,
+        }
+    }
+
+    /// The machine as of the last output.
+    pub fn intcode(&self) -> &IntCode<C> {
+        &self.intcode
+    }
+
+    /// Gives the machine back, to carry on some other way.
+    pub fn into_intcode(self) -> IntCode<C> {
+        self.intcode
+    }
+
+    /// `None` while the program can still output.
+    pub fn end(&self) -> Option<Result<(), IntCodeError>> {
+        self.end
+    }
+
+    /// Groups the outputs into records of `N` values. A partial record left when the
+    /// program stops is dropped.
+    pub fn tuples<const N: usize>(self) -> Tuples<Self, N> {
+        Tuples(self)
+    }
+
+    fn next_output(&mut self) -> Result<Option<C>, IntCodeError> {
+        loop {
+            match self.intcode.resume()? {
+                StepResult::NeedsInput => match (self.input)() {
+                    Some(value) => self.intcode.provide_input(value),
+                    None => {
+                        return Err(IntCodeError::InputExhausted {
+                            pc: self.intcode.pc,
+                            instruction: self.intcode.memory[self.intcode.pc].saturating_i64(),
+                        })
+                    }
+                },
+                StepResult::Output(value) => return Ok(Some(value)),
+                StepResult::Halted => return Ok(None),
+                StepResult::Interrupted(interrupt) => {
+                    return Err(self.intcode.interrupted(interrupt))
+                }
+            }
+        }
+    }
+}
+
+impl<I: FnMut() -> Option<C>, C: Cell> Iterator for Outputs<I, C> {
+    type Item = C;
+
+    fn next(&mut self) -> Option<C> {
+        if self.end.is_some() {
+            return None;
+        }
+
+        match self.next_output() {
+            Ok(Some(value)) => Some(value),
+            Ok(None) => {
+                self.end = Some(Ok(()));
+                None
+            }
+            Err(error) => {
+                self.end = Some(Err(error));
+                None
+            }
+        }
+    }
+}
+
+impl<C: Cell> IntCode<C> {
+    /// Iterates over what the machine outputs from here on. See [`Outputs`].
+    pub fn outputs<I: FnMut() -> Option<C>>(self, input: I) -> Outputs<I, C> {
+        Outputs::new(self, input)
+    }
+}
+
+/// Fixed size records from the outputs of a machine.
+pub struct Tuples<T, const N: usize>(T);
+
+impl<T, const N: usize> Tuples<T, N> {
+    /// The iterator the records are taken from.
+    pub fn inner(&self) -> &T {
+        &self.0
+    }
+
+    /// Gives back the iterator the records are taken from.
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T: Iterator, const N: usize> Iterator for Tuples<T, N>
+where
+    T::Item: Default,
+{
+    type Item = [T::Item; N];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut record: [T::Item; N] = std::array::from_fn(|_| T::Item::default());
+        for value in record.iter_mut() {
+            *value = self.0.next()?;
+        }
+        Some(record)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lazy() {
+        // Echoes its input until it reads a zero.
+        let intcode = IntCode::assemble(
+            "
+            loop:
+                in    [value]
+                jf    [value], #done
+                out   [value]
+                jt    #1, #loop
+            done:
+                hlt
+            value: .data 0
+            ",
+        )
+        .unwrap();
+
+        let mut read = 0;
+        let mut outputs = intcode.outputs(|| {
+            read += 1;
+            Some(read)
+        });
+        assert_eq!(outputs.by_ref().take(3).collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert_eq!(outputs.end(), None);
+        assert_eq!(outputs.intcode().pending_inputs().count(), 0);
+
+        let mut inputs = vec![5, 0].into_iter();
+        let mut outputs = outputs.into_intcode().outputs(|| inputs.next());
+        assert_eq!(outputs.by_ref().collect::<Vec<_>>(), vec![5]);
+        assert_eq!(outputs.end(), Some(Ok(())));
+        assert_eq!(outputs.next(), None);
+    }
+
+    #[test]
+    fn failures() {
+        let intcode = "104,1,104,2,104,3,3,0,99".parse::<IntCode>().unwrap();
+        let mut pairs = intcode.outputs(|| None).tuples::<2>();
+        assert_eq!(pairs.by_ref().collect::<Vec<_>>(), vec![[1, 2]]);
+        assert_eq!(
+            pairs.inner().end(),
+            Some(Err(IntCodeError::InputExhausted {
+                pc: 6,
+                instruction: 3
+            }))
+        );
+    }
+}
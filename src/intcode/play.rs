@@ -0,0 +1,359 @@
+//! Interactive play of ASCII and arcade programs, for the `play` binary.
+//!
+//! ASCII programs read a line at a time. Breakout reads single keys, so the binary
+//! switches the terminal out of line mode with `stty` while it plays.
+
+use super::*;
+use std::collections::HashMap;
+use std::io::{self, BufRead, IsTerminal, Read, Write};
+use std::process::{Command, Stdio};
+
+pub trait Renderer {
+    fn output(&mut self, value: IntCodeCell);
+
+    // Text to show the player when the program blocks or halts.
+    fn render(&mut self) -> String;
+
+    fn input(&mut self, line: &str) -> Result<Vec<IntCodeCell>, String>;
+
+    // Whether each render replaces the whole screen rather than continuing the text.
+    fn redraws(&self) -> bool {
+        false
+    }
+
+    // Whether each key is a whole input, without waiting for enter.
+    fn keypresses(&self) -> bool {
+        false
+    }
+}
+
+#[derive(Default)]
+pub struct AsciiRenderer {
+    text: String,
+}
+
+impl AsciiRenderer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Renderer for AsciiRenderer {
+    fn output(&mut self, value: IntCodeCell) {
+        match ascii::ascii_char(value) {
+            Some(c) => self.text.push(c),
+            None => self.text.push_str(&format!("[{}]\n", value)),
+        }
+    }
+
+    fn render(&mut self) -> String {
+        std::mem::take(&mut self.text)
+    }
+
+    fn input(&mut self, line: &str) -> Result<Vec<IntCodeCell>, String> {
+        if !line.is_ascii() {
+            return Err("input must be ascii".to_string());
+        }
+        Ok(line
+            .bytes()
+            .chain(Some(b'\n'))
+            .map(IntCodeCell::from)
+            .collect())
+    }
+}
+
+#[derive(Default)]
+pub struct BreakoutRenderer {
+    tiles: HashMap<(IntCodeCell, IntCodeCell), IntCodeCell>,
+    score: IntCodeCell,
+    pending: Vec<IntCodeCell>,
+}
+
+impl BreakoutRenderer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn score(&self) -> IntCodeCell {
+        self.score
+    }
+}
+
+impl Renderer for BreakoutRenderer {
+    fn output(&mut self, value: IntCodeCell) {
+        self.pending.push(value);
+        if let [x, y, tile] = self.pending[..] {
+            self.pending.clear();
+            if (x, y) == (-1, 0) {
+                self.score = tile;
+            } else {
+                self.tiles.insert((x, y), tile);
+            }
+        }
+    }
+
+    fn render(&mut self) -> String {
+        let width = self.tiles.keys().map(|&(x, _)| x + 1).max().unwrap_or(0);
+        let height = self.tiles.keys().map(|&(_, y)| y + 1).max().unwrap_or(0);
+        let mut screen = format!("score {}\n", self.score);
+
+        for y in 0..height {
+            for x in 0..width {
+                screen.push(match self.tiles.get(&(x, y)) {
+                    Some(1) => '#',
+                    Some(2) => '=',
+                    Some(3) => '-',
+                    Some(4) => 'o',
+                    _ => ' ',
+                });
+            }
+            screen.push('\n');
+        }
+
+        screen.push_str("a: left, s: stay, d: right\n");
+        screen
+    }
+
+    fn input(&mut self, line: &str) -> Result<Vec<IntCodeCell>, String> {
+        match line.trim() {
+            "a" | "h" => Ok(vec![-1]),
+            "" | "s" | "j" => Ok(vec![0]),
+            "d" | "l" => Ok(vec![1]),
+            other => Err(format!("unknown move {:?}", other)),
+        }
+    }
+
+    fn redraws(&self) -> bool {
+        true
+    }
+
+    fn keypresses(&self) -> bool {
+        true
+    }
+}
+
+#[derive(Debug)]
+pub enum PlayError {
+    Io(io::Error),
+    IntCode(IntCodeError),
+}
+
+impl fmt::Display for PlayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PlayError::Io(error) => write!(f, "{}", error),
+            PlayError::IntCode(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for PlayError {}
+
+impl From<io::Error> for PlayError {
+    fn from(error: io::Error) -> Self {
+        PlayError::Io(error)
+    }
+}
+
+impl From<IntCodeError> for PlayError {
+    fn from(error: IntCodeError) -> Self {
+        PlayError::IntCode(error)
+    }
+}
+
+/// Puts the terminal into reading single keys without echoing them, until dropped.
+/// Ctrl-C and ctrl-D arrive as keys, so `play` can stop and restore the terminal.
+/// Does nothing when stdin isn't a terminal.
+pub struct RawTerminal {
+    saved: Option<String>,
+}
+
+impl RawTerminal {
+    pub fn new() -> io::Result<Self> {
+        if !io::stdin().is_terminal() {
+            return Ok(Self { saved: None });
+        }
+
+        let saved = stty(&["-g"])?;
+        stty(&["-icanon", "-echo", "-isig", "min", "1"])?;
+        Ok(Self {
+            saved: Some(saved.trim().to_string()),
+        })
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        if let Some(saved) = &self.saved {
+            let _ = stty(&[saved]);
+        }
+    }
+}
+
+// stty works on its stdin, which `output` would otherwise replace.
+fn stty(args: &[&str]) -> io::Result<String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+// None at the end of input.
+fn read_line(input: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(line.trim_end_matches(&['\r', '\n'][..]).to_string()))
+}
+
+// None at the end of input or on ctrl-C or ctrl-D. Enter reads as an empty input.
+fn read_key(input: &mut impl BufRead) -> io::Result<Option<String>> {
+    match input.bytes().next().transpose()? {
+        None | Some(3) | Some(4) => Ok(None),
+        Some(b'\r') | Some(b'\n') => Ok(Some(String::new())),
+        Some(key) => Ok(Some(char::from(key).to_string())),
+    }
+}
+
+/// Returns true if the program halted, false if the player ran out of input first.
+pub fn play(
+    mut intcode: IntCode,
+    renderer: &mut dyn Renderer,
+    mut input: impl BufRead,
+    mut output: impl Write,
+) -> Result<bool, PlayError> {
+    loop {
+        let result = intcode.resume()?;
+        match result {
+            StepResult::Output(value) => {
+                renderer.output(value);
+                continue;
+            }
+            StepResult::Interrupted(interrupt) => {
+                return Err(intcode.interrupted(interrupt).into())
+            }
+            StepResult::NeedsInput | StepResult::Halted => (),
+        }
+
+        if renderer.redraws() {
+            write!(output, "\x1b[2J\x1b[H")?;
+        }
+        write!(output, "{}", renderer.render())?;
+
+        if result == StepResult::Halted {
+            output.flush()?;
+            return Ok(true);
+        }
+
+        loop {
+            write!(output, "> ")?;
+            output.flush()?;
+
+            let line = if renderer.keypresses() {
+                read_key(&mut input)?
+            } else {
+                read_line(&mut input)?
+            };
+            let line = match line {
+                Some(line) => line,
+                None => return Ok(false),
+            };
+
+            match renderer.input(&line) {
+                Ok(values) => {
+                    values
+                        .into_iter()
+                        .for_each(|value| intcode.provide_input(value));
+                    break;
+                }
+                Err(error) => writeln!(output, "{}", error)?,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ascii() {
+        let echo = IntCode::assemble(
+            "
+            loop:
+                in    [c]
+                eq    [c], #10, [done]
+                jt    [done], #end
+                out   [c]
+                jt    #1, #loop
+            end:
+                out   #10
+                out   #1000
+                hlt
+            c:    .data 0
+            done: .data 0
+            ",
+        )
+        .unwrap();
+
+        let mut output = Vec::new();
+        let halted = play(echo, &mut AsciiRenderer::new(), &b"hi\n"[..], &mut output).unwrap();
+        assert!(halted);
+        assert_eq!(String::from_utf8(output).unwrap(), "> hi\n[1000]\n");
+    }
+
+    #[test]
+    fn breakout() {
+        let mut renderer = BreakoutRenderer::new();
+        for &value in &[0, 0, 1, 1, 0, 1, 2, 0, 1, 1, 1, 4, 2, 1, 3, -1, 0, 7] {
+            renderer.output(value);
+        }
+
+        assert_eq!(renderer.score(), 7);
+        assert_eq!(
+            renderer.render(),
+            "score 7\n###\n o-\na: left, s: stay, d: right\n"
+        );
+        assert_eq!(renderer.input("a"), Ok(vec![-1]));
+        assert_eq!(renderer.input(""), Ok(vec![0]));
+        assert_eq!(renderer.input("d"), Ok(vec![1]));
+        assert!(renderer.input("x").is_err());
+    }
+
+    #[test]
+    fn keypresses() {
+        // Shows each move as the score.
+        let moves = IntCode::assemble(
+            "
+            loop:
+                in    [move]
+                out   #-1
+                out   #0
+                out   [move]
+                jt    #1, #loop
+            move: .data 0
+            ",
+        )
+        .unwrap();
+
+        let mut renderer = BreakoutRenderer::new();
+        let mut output = Vec::new();
+        let halted = play(moves.clone(), &mut renderer, &b"da\n"[..], &mut output).unwrap();
+        assert!(!halted);
+        assert_eq!(renderer.score(), 0);
+        assert!(String::from_utf8(output).unwrap().contains("score -1\n"));
+
+        // Ctrl-C stops before the keys after it.
+        let mut renderer = BreakoutRenderer::new();
+        let halted = play(moves, &mut renderer, &b"a\x03d"[..], io::sink()).unwrap();
+        assert!(!halted);
+        assert_eq!(renderer.score(), -1);
+    }
+}
@@ -0,0 +1,199 @@
+//! Running many variants of a program in parallel to find the one wanted.
+
+use super::memo::RunResult;
+use super::*;
+use rayon::prelude::*;
+
+/// Changes to make to a program before one run of a search.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Candidate {
+    /// Cells to overwrite, as `(address, value)` pairs.
+    pub patches: Vec<(usize, IntCodeCell)>,
+    /// Input to queue, after any the base builder already has.
+    pub inputs: Vec<IntCodeCell>,
+}
+
+impl Candidate {
+    /// A candidate that only patches cells.
+    pub fn patches(patches: Vec<(usize, IntCodeCell)>) -> Self {
+        Self {
+            patches,
+            ..Self::default()
+        }
+    }
+
+    /// A candidate that only queues input.
+    pub fn inputs(inputs: Vec<IntCodeCell>) -> Self {
+        Self {
+            inputs,
+            ..Self::default()
+        }
+    }
+
+    /// `base` with this candidate's patches and input added.
+    pub fn apply(&self, base: &IntCodeBuilder) -> IntCodeBuilder {
+        self.patches
+            .iter()
+            .fold(base.clone(), |builder, &(address, value)| {
+                builder.patch(address, value)
+            })
+            .inputs(self.inputs.iter().copied())
+    }
+}
+
+/// Evaluates candidates across the rayon thread pool, returning the earliest one, in
+/// the order `candidates` yields them, that evaluates to something. Candidates after
+/// it may be skipped.
+pub fn find_first<T: Send, R: Send>(
+    candidates: impl IntoParallelIterator<Item = T>,
+    evaluate: impl Fn(&T) -> Option<R> + Sync + Send,
+) -> Option<(T, R)> {
+    candidates
+        .into_par_iter()
+        .find_map_first(|candidate| evaluate(&candidate).map(|result| (candidate, result)))
+}
+
+/// The candidate with the highest score, or the last of them on a tie. Candidates
+/// scoring `None` are left out.
+pub fn max_by_key<T: Send, K: Ord + Send>(
+    candidates: impl IntoParallelIterator<Item = T>,
+    score: impl Fn(&T) -> Option<K> + Sync + Send,
+) -> Option<(T, K)> {
+    scored(candidates, score).max_by(|(_, a), (_, b)| a.cmp(b))
+}
+
+/// The candidate with the lowest score, or the first of them on a tie.
+pub fn min_by_key<T: Send, K: Ord + Send>(
+    candidates: impl IntoParallelIterator<Item = T>,
+    score: impl Fn(&T) -> Option<K> + Sync + Send,
+) -> Option<(T, K)> {
+    scored(candidates, score).min_by(|(_, a), (_, b)| a.cmp(b))
+}
+
+fn scored<T: Send, K: Send>(
+    candidates: impl IntoParallelIterator<Item = T>,
+    score: impl Fn(&T) -> Option<K> + Sync + Send,
+) -> impl ParallelIterator<Item = (T, K)> {
+    candidates
+        .into_par_iter()
+        .filter_map(move |candidate| score(&candidate).map(|key| (candidate, key)))
+}
+
+// Runs that fail just don't match or score.
+impl IntCodeBuilder {
+    /// Runs the program once per candidate, returning the earliest candidate whose
+    /// run `accept` takes.
+    pub fn search_first(
+        &self,
+        candidates: impl IntoParallelIterator<Item = Candidate>,
+        accept: impl Fn(&RunResult) -> bool + Sync + Send,
+    ) -> Option<Candidate> {
+        find_first(candidates, |candidate| {
+            let result = candidate.apply(self).run_memoized().ok()?;
+            if accept(&result) {
+                Some(())
+            } else {
+                None
+            }
+        })
+        .map(|(candidate, ())| candidate)
+    }
+
+    /// Runs the program once per candidate, returning the candidate whose run scores
+    /// highest.
+    pub fn search_max<K: Ord + Send>(
+        &self,
+        candidates: impl IntoParallelIterator<Item = Candidate>,
+        score: impl Fn(&RunResult) -> K + Sync + Send,
+    ) -> Option<(Candidate, K)> {
+        max_by_key(candidates, |candidate| {
+            let result = candidate.apply(self).run_memoized().ok()?;
+            Some(score(&result))
+        })
+    }
+
+    /// Runs the program once per candidate, returning the candidate whose run scores
+    /// lowest.
+    pub fn search_min<K: Ord + Send>(
+        &self,
+        candidates: impl IntoParallelIterator<Item = Candidate>,
+        score: impl Fn(&RunResult) -> K + Sync + Send,
+    ) -> Option<(Candidate, K)> {
+        min_by_key(candidates, |candidate| {
+            let result = candidate.apply(self).run_memoized().ok()?;
+            Some(score(&result))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generic() {
+        let squares = |&n: &i64| if n * n > 50 { Some(n * n) } else { None };
+        assert_eq!(find_first(0..100, squares), Some((8, 64)));
+        assert_eq!(find_first(0..5, squares), None);
+
+        let wave = |&n: &i64| {
+            if n == 3 {
+                None
+            } else {
+                Some((n % 4 - 2).abs())
+            }
+        };
+        assert_eq!(max_by_key(0..10, wave), Some((8, 2)));
+        assert_eq!(min_by_key(0..10, wave), Some((2, 0)));
+        assert_eq!(max_by_key(3..4, wave), None);
+    }
+
+    #[test]
+    fn programs() {
+        // Outputs its input times the factor, or fails on a negative input.
+        let program = IntCode::assemble(
+            "
+                in    [value]
+                lt    [value], #0, [negative]
+                jt    [negative], #0
+                mul   [value], [factor], [value]
+                out   [value]
+                hlt
+            value:    .data 0
+            negative: .data 0
+            factor:   .data 3
+            ",
+        )
+        .unwrap();
+        let base = IntCode::builder(program.program().to_vec());
+        let by_input = |value| Candidate::inputs(vec![value]);
+
+        let found = base.search_first((-50..50).into_par_iter().map(by_input), |result| {
+            result.outputs == vec![21]
+        });
+        assert_eq!(found, Some(by_input(7)));
+
+        let values = vec![-10, 4, -2, 9, 1];
+        let (candidate, score) = base
+            .search_max(values.clone().into_par_iter().map(by_input), |result| {
+                result.outputs[0]
+            })
+            .unwrap();
+        assert_eq!((candidate, score), (by_input(9), 27));
+        let (candidate, score) = base
+            .search_min(values.into_par_iter().map(by_input), |result| {
+                result.outputs[0]
+            })
+            .unwrap();
+        assert_eq!((candidate, score), (by_input(1), 3));
+
+        let patched = Candidate {
+            patches: vec![(18, -1)],
+            inputs: vec![6],
+        };
+        assert_eq!(
+            patched.apply(&base).run_memoized().unwrap().outputs,
+            vec![-6]
+        );
+    }
+}
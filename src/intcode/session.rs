@@ -0,0 +1,484 @@
+//! Recording a run's input and output so it can be replayed and checked.
+
+use super::*;
+
+const HEADER: &str = "intcode-session 1";
+
+pub(super) const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// A value read or written during a recorded run. `at` is the number of
+/// instructions run before the one reading or writing the value.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+#[allow(missing_docs)]
+pub enum SessionEvent {
+    /// The program read `value`.
+    Input { at: u64, value: IntCodeCell },
+    /// The program wrote `value`.
+    Output { at: u64, value: IntCodeCell },
+}
+
+/// A recorded run, saved and loaded through `Display` and `FromStr`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Session {
+    /// The state hash of the machine when recording started.
+    pub start_hash: u64,
+    /// The state hash of the machine when recording finished.
+    pub end_hash: u64,
+    /// Every read and write, in order.
+    pub events: Vec<SessionEvent>,
+    /// How many instructions ran.
+    pub instructions: u64,
+    /// Whether the run ended by halting.
+    pub halted: bool,
+}
+
+/// Why a session couldn't be loaded.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SessionError {
+    /// The text doesn't start with the session header.
+    BadHeader,
+    /// A line that can't be parsed.
+    BadLine {
+        /// Counting from 1.
+        line: usize,
+    },
+    /// A required line was missing; holds the keyword it starts with.
+    MissingField(&'static str),
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SessionError::BadHeader => write!(f, "not an intcode session"),
+            SessionError::BadLine { line } => write!(f, "line {}: bad entry", line),
+            SessionError::MissingField(field) => write!(f, "missing field {:?}", field),
+        }
+    }
+}
+
+impl std::error::Error for SessionError {}
+
+/// How a replay differed from the recording. Each variant has what the recording
+/// `expected` and the `actual` value from the replay.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[allow(missing_docs)]
+pub enum ReplayError {
+    /// The machine's state hash differs before anything runs.
+    WrongStart { expected: u64, actual: u64 },
+    /// The event at `index` differs; `None` is an event missing from one side.
+    Diverged {
+        index: usize,
+        expected: Option<SessionEvent>,
+        actual: Option<SessionEvent>,
+    },
+    /// The events match but the final state or the instruction count doesn't.
+    WrongEnd {
+        expected: Box<Session>,
+        actual: Box<Session>,
+    },
+    /// The machine faulted.
+    IntCode(IntCodeError),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplayError::WrongStart { expected, actual } => write!(
+                f,
+                "machine state {:016x} doesn't match recorded {:016x}",
+                actual, expected
+            ),
+            ReplayError::Diverged {
+                index,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "event {} diverged: expected {:?}, got {:?}",
+                index, expected, actual
+            ),
+            ReplayError::WrongEnd { expected, actual } => write!(
+                f,
+                "final state {:016x} after {} instructions doesn't match recorded {:016x} after {}",
+                actual.end_hash, actual.instructions, expected.end_hash, expected.instructions
+            ),
+            ReplayError::IntCode(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+impl From<IntCodeError> for ReplayError {
+    fn from(error: IntCodeError) -> Self {
+        ReplayError::IntCode(error)
+    }
+}
+
+pub(super) fn fnv(hash: u64, value: IntCodeCell) -> u64 {
+    value.to_le_bytes().iter().fold(hash, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME)
+    })
+}
+
+impl IntCode {
+    /// FNV-1a over everything a snapshot holds.
+    pub fn state_hash(&self) -> u64 {
+        let snapshot = self.snapshot();
+        let mut hash = FNV_OFFSET;
+
+        for list in &[&snapshot.program, &snapshot.inputs] {
+            hash = fnv(hash, list.len() as IntCodeCell);
+            hash = list.iter().fold(hash, |hash, &value| fnv(hash, value));
+        }
+        hash = fnv(hash, snapshot.extra.len() as IntCodeCell);
+        for (&address, &value) in &snapshot.extra {
+            hash = fnv(fnv(hash, address as IntCodeCell), value);
+        }
+        hash = fnv(hash, snapshot.pc as IntCodeCell);
+        fnv(hash, snapshot.relative_base)
+    }
+}
+
+/// Records every value a machine reads or writes while traced with it.
+pub struct Recorder {
+    start_hash: u64,
+    events: Vec<SessionEvent>,
+    instructions: u64,
+    inputs: usize,
+    halted: bool,
+}
+
+impl Recorder {
+    /// Starts recording from the machine as it is.
+    pub fn new(intcode: &IntCode) -> Self {
+        Self {
+            start_hash: intcode.state_hash(),
+            events: Vec::new(),
+            instructions: 0,
+            inputs: 0,
+            halted: false,
+        }
+    }
+
+    /// Everything recorded so far.
+    pub fn events(&self) -> &[SessionEvent] {
+        &self.events
+    }
+
+    /// Ends the recording, with `intcode` as the final state.
+    pub fn finish(self, intcode: &IntCode) -> Session {
+        Session {
+            start_hash: self.start_hash,
+            end_hash: intcode.state_hash(),
+            events: self.events,
+            instructions: self.instructions,
+            halted: self.halted,
+        }
+    }
+}
+
+impl Tracer for Recorder {
+    fn trace(&mut self, event: &TraceEvent) {
+        let at = self.instructions;
+        match event.opcode {
+            Input => {
+                if let Some(write) = &event.write {
+                    self.events.push(SessionEvent::Input {
+                        at,
+                        value: write.new,
+                    });
+                    self.inputs += 1;
+                }
+            }
+            Output => {
+                if let Some(value) = event.operands[0] {
+                    self.events.push(SessionEvent::Output { at, value });
+                }
+            }
+            Terminate => self.halted = true,
+            _ => (),
+        }
+        self.instructions += 1;
+    }
+}
+
+impl Session {
+    /// The values the program read, in order.
+    pub fn inputs(&self) -> impl Iterator<Item = IntCodeCell> + '_ {
+        self.events.iter().filter_map(|event| match *event {
+            SessionEvent::Input { value, .. } => Some(value),
+            SessionEvent::Output { .. } => None,
+        })
+    }
+
+    /// Runs `intcode` on the recorded inputs, failing at the first event or final
+    /// state that doesn't match. Replaces the machine's run limits so that a replay
+    /// can't run past the end of the recording.
+    pub fn replay(&self, mut intcode: IntCode) -> Result<(), ReplayError> {
+        let start_hash = intcode.state_hash();
+        if start_hash != self.start_hash {
+            return Err(ReplayError::WrongStart {
+                expected: self.start_hash,
+                actual: start_hash,
+            });
+        }
+
+        let inputs = self.inputs().collect::<Vec<_>>();
+        let mut recorder = Recorder::new(&intcode);
+        intcode.set_run_limits(RunLimits {
+            instruction_budget: Some(self.instructions),
+            ..RunLimits::default()
+        });
+
+        let mut checked = 0;
+        loop {
+            let result = intcode.resume_traced(&mut recorder)?;
+            self.check_events(recorder.events(), checked, false)?;
+            checked = recorder.events().len();
+
+            match result {
+                StepResult::NeedsInput => match inputs.get(recorder.inputs) {
+                    Some(&value) => intcode.provide_input(value),
+                    None => break,
+                },
+                StepResult::Output(_) => (),
+                StepResult::Halted | StepResult::Interrupted(_) => break,
+            }
+        }
+
+        let session = recorder.finish(&intcode);
+        self.check_events(&session.events, checked, true)?;
+        if session != *self {
+            return Err(ReplayError::WrongEnd {
+                expected: Box::new(self.clone()),
+                actual: Box::new(session),
+            });
+        }
+        Ok(())
+    }
+
+    // Everything before `from` is already known to match.
+    fn check_events(
+        &self,
+        events: &[SessionEvent],
+        from: usize,
+        finished: bool,
+    ) -> Result<(), ReplayError> {
+        let len = if finished {
+            std::cmp::max(events.len(), self.events.len())
+        } else {
+            events.len()
+        };
+
+        match (from..len).find(|&index| events.get(index) != self.events.get(index)) {
+            Some(index) => Err(ReplayError::Diverged {
+                index,
+                expected: self.events.get(index).copied(),
+                actual: events.get(index).copied(),
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for Session {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", HEADER)?;
+        writeln!(f, "start {:016x}", self.start_hash)?;
+        for event in &self.events {
+            match event {
+                SessionEvent::Input { at, value } => writeln!(f, "in {} {}", at, value)?,
+                SessionEvent::Output { at, value } => writeln!(f, "out {} {}", at, value)?,
+            }
+        }
+        writeln!(
+            f,
+            "end {:016x} {} {}",
+            self.end_hash,
+            self.instructions,
+            if self.halted { "halted" } else { "running" }
+        )
+    }
+}
+
+impl std::str::FromStr for Session {
+    type Err = SessionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s.lines().enumerate().map(|(line, text)| (line + 1, text));
+        if lines.next().map(|(_, text)| text.trim()) != Some(HEADER) {
+            return Err(SessionError::BadHeader);
+        }
+
+        let (mut start, mut end) = (None, None);
+        let mut events = Vec::new();
+
+        for (line, text) in lines {
+            let fields = text.split_whitespace().collect::<Vec<_>>();
+            let bad_line = SessionError::BadLine { line };
+            let hash = |field: &str| u64::from_str_radix(field, 16).map_err(|_| bad_line);
+
+            match fields[..] {
+                [] => (),
+                ["start", start_hash] => start = Some(hash(start_hash)?),
+                ["in", at, value] | ["out", at, value] => {
+                    let at = at.parse().map_err(|_| bad_line)?;
+                    let value = value.parse().map_err(|_| bad_line)?;
+                    events.push(if fields[0] == "in" {
+                        SessionEvent::Input { at, value }
+                    } else {
+                        SessionEvent::Output { at, value }
+                    });
+                }
+                ["end", end_hash, instructions, state] => {
+                    let halted = match state {
+                        "halted" => true,
+                        "running" => false,
+                        _ => return Err(bad_line),
+                    };
+                    let instructions = instructions.parse().map_err(|_| bad_line)?;
+                    end = Some((hash(end_hash)?, instructions, halted));
+                }
+                _ => return Err(bad_line),
+            }
+        }
+
+        let start_hash = start.ok_or(SessionError::MissingField("start"))?;
+        let (end_hash, instructions, halted) = end.ok_or(SessionError::MissingField("end"))?;
+        Ok(Self {
+            start_hash,
+            end_hash,
+            events,
+            instructions,
+            halted,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cmp::Ordering;
+
+    fn echo() -> IntCode {
+        IntCode::assemble(
+            "
+            loop:
+                in    [value]
+                jf    [value], #end
+                mul   [value], #2, [value]
+                out   [value]
+                jt    #1, #loop
+            end:
+                hlt
+            value: .data 0
+            ",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut intcode = echo();
+        let mut recorder = Recorder::new(&intcode);
+        intcode.provide_input(3);
+        intcode.provide_input(0);
+        while intcode.resume_traced(&mut recorder) != Ok(StepResult::Halted) {}
+
+        let session = recorder.finish(&intcode);
+        let text = session.to_string();
+        assert_eq!(
+            text,
+            format!(
+                "intcode-session 1
+start {:016x}
+in 0 3
+out 3 6
+in 5 0
+end {:016x} 8 halted
+",
+                echo().state_hash(),
+                intcode.state_hash()
+            )
+        );
+        assert_eq!(text.parse::<Session>(), Ok(session.clone()));
+        assert_eq!(session.replay(echo()), Ok(()));
+
+        let mut other = echo();
+        other.provide_input(1);
+        assert!(matches!(
+            session.replay(other),
+            Err(ReplayError::WrongStart { .. })
+        ));
+
+        let mut patched = echo();
+        patched.replace_cell(7, 3);
+        let mut session = session;
+        session.start_hash = patched.state_hash();
+        assert_eq!(
+            session.replay(patched),
+            Err(ReplayError::Diverged {
+                index: 1,
+                expected: Some(SessionEvent::Output { at: 3, value: 6 }),
+                actual: Some(SessionEvent::Output { at: 3, value: 9 }),
+            })
+        );
+    }
+
+    #[test]
+    fn breakout() {
+        let mut intcode = include_str!("../../input/2019/day13.txt")
+            .trim()
+            .parse::<IntCode>()
+            .unwrap();
+        intcode.replace_cell(0, 2);
+        let start = intcode.clone();
+
+        // Draws the screen, plays some moves and stops partway through.
+        let mut recorder = Recorder::new(&intcode);
+        let (mut outputs, mut paddle_x, mut ball_x) = (Vec::new(), 0, 0);
+        for _ in 0..5000 {
+            match intcode.resume_traced(&mut recorder).unwrap() {
+                StepResult::NeedsInput => intcode.provide_input(match paddle_x.cmp(&ball_x) {
+                    Ordering::Less => 1,
+                    Ordering::Equal => 0,
+                    Ordering::Greater => -1,
+                }),
+                StepResult::Output(value) => {
+                    outputs.push(value);
+                    if let [x, _, tile] = outputs[..] {
+                        match tile {
+                            3 => paddle_x = x,
+                            4 => ball_x = x,
+                            _ => (),
+                        }
+                        outputs.clear();
+                    }
+                }
+                _ => unreachable!(),
+            }
+        }
+
+        let session = recorder.finish(&intcode);
+        assert!(!session.halted);
+        assert!(session.inputs().count() > 0);
+        let session = session.to_string().parse::<Session>().unwrap();
+        assert_eq!(session.replay(start), Ok(()));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!("end".parse::<Session>(), Err(SessionError::BadHeader));
+        assert_eq!(
+            "intcode-session 1\nstart 0\nin 1".parse::<Session>(),
+            Err(SessionError::BadLine { line: 3 })
+        );
+        assert_eq!(
+            "intcode-session 1\nstart 0".parse::<Session>(),
+            Err(SessionError::MissingField("end"))
+        );
+    }
+}
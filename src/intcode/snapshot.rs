@@ -0,0 +1,238 @@
+//! Saving a machine's state as text and restoring it.
+
+use super::*;
+use std::collections::BTreeMap;
+
+const HEADER: &str = "intcode-snapshot 1";
+
+/// Everything needed to recreate a machine, saved and loaded through `Display` and
+/// `FromStr`. Run limits and the engine aren't kept.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Snapshot {
+    /// The cells the program was loaded into, as they are now.
+    pub program: Vec<IntCodeCell>,
+    /// Nonzero cells past the end of the program.
+    pub extra: BTreeMap<usize, IntCodeCell>,
+    /// The address of the next instruction.
+    pub pc: usize,
+    /// The relative base.
+    pub relative_base: IntCodeCell,
+    /// Input queued but not read yet.
+    pub inputs: Vec<IntCodeCell>,
+}
+
+/// Why a snapshot couldn't be loaded. Lines count from 1.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[allow(missing_docs)]
+pub enum SnapshotError {
+    /// The text doesn't start with the snapshot header.
+    BadHeader,
+    /// A line starting with a name that isn't a field.
+    UnknownField { line: usize },
+    /// A field whose value can't be parsed.
+    BadValue { line: usize },
+    /// A required field was missing; holds its name.
+    MissingField(&'static str),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::BadHeader => write!(f, "not an intcode snapshot"),
+            SnapshotError::UnknownField { line } => write!(f, "line {}: unknown field", line),
+            SnapshotError::BadValue { line } => write!(f, "line {}: bad value", line),
+            SnapshotError::MissingField(field) => write!(f, "missing field {:?}", field),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl IntCode {
+    /// The machine's state as it is now.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            program: self.memory.starting_memory.clone(),
+            extra: self.memory.extra().collect(),
+            pc: self.pc,
+            relative_base: self.relative_base,
+            inputs: self.inputs.iter().copied().collect(),
+        }
+    }
+
+    /// A machine in the state `snapshot` was taken in, using the default engine and
+    /// no limits.
+    pub fn restore(snapshot: &Snapshot) -> Self {
+        let mut intcode = Self::new(snapshot.program.clone());
+
+        for (&address, &value) in &snapshot.extra {
+            intcode.memory[address] = value;
+        }
+        intcode.pc = snapshot.pc;
+        intcode.relative_base = snapshot.relative_base;
+        intcode.inputs = snapshot.inputs.iter().copied().collect();
+
+        intcode
+    }
+}
+
+fn write_list<T: fmt::Display>(
+    f: &mut fmt::Formatter,
+    name: &str,
+    values: impl IntoIterator<Item = T>,
+) -> fmt::Result {
+    write!(f, "{}", name)?;
+    for (index, value) in values.into_iter().enumerate() {
+        write!(f, "{}{}", if index == 0 { ' ' } else { ',' }, value)?;
+    }
+    writeln!(f)
+}
+
+impl fmt::Display for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", HEADER)?;
+        writeln!(f, "pc {}", self.pc)?;
+        writeln!(f, "relative_base {}", self.relative_base)?;
+        write_list(f, "inputs", &self.inputs)?;
+        write_list(f, "program", &self.program)?;
+        write_list(
+            f,
+            "extra",
+            self.extra
+                .iter()
+                .map(|(address, value)| format!("{}={}", address, value)),
+        )
+    }
+}
+
+fn parse_list<T>(
+    line: usize,
+    values: &str,
+    parse: impl Fn(&str) -> Option<T>,
+) -> Result<Vec<T>, SnapshotError> {
+    if values.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    values
+        .split(',')
+        .map(|value| parse(value).ok_or(SnapshotError::BadValue { line }))
+        .collect()
+}
+
+impl std::str::FromStr for Snapshot {
+    type Err = SnapshotError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s.lines().enumerate().map(|(line, text)| (line + 1, text));
+        if lines.next().map(|(_, text)| text.trim()) != Some(HEADER) {
+            return Err(SnapshotError::BadHeader);
+        }
+
+        let (mut pc, mut relative_base, mut inputs, mut program, mut extra) =
+            (None, None, None, None, None);
+
+        for (line, text) in lines {
+            let text = text.trim();
+            if text.is_empty() {
+                continue;
+            }
+
+            let (name, values) = match text.find(' ') {
+                Some(split) => (&text[..split], text[split + 1..].trim()),
+                None => (text, ""),
+            };
+            let bad_value = SnapshotError::BadValue { line };
+
+            match name {
+                "pc" => pc = Some(values.parse().map_err(|_| bad_value)?),
+                "relative_base" => relative_base = Some(values.parse().map_err(|_| bad_value)?),
+                "inputs" => inputs = Some(parse_list(line, values, |v| v.parse().ok())?),
+                "program" => program = Some(parse_list(line, values, |v| v.parse().ok())?),
+                "extra" => {
+                    extra = Some(
+                        parse_list(line, values, |v| {
+                            let split = v.find('=')?;
+                            Some((v[..split].parse().ok()?, v[split + 1..].parse().ok()?))
+                        })?
+                        .into_iter()
+                        .collect(),
+                    )
+                }
+                _ => return Err(SnapshotError::UnknownField { line }),
+            }
+        }
+
+        Ok(Self {
+            pc: pc.ok_or(SnapshotError::MissingField("pc"))?,
+            relative_base: relative_base.ok_or(SnapshotError::MissingField("relative_base"))?,
+            inputs: inputs.ok_or(SnapshotError::MissingField("inputs"))?,
+            program: program.ok_or(SnapshotError::MissingField("program"))?,
+            extra: extra.ok_or(SnapshotError::MissingField("extra"))?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut intcode = IntCode::assemble(
+            "
+                arb   #1000
+            loop:
+                in    rb+0
+                out   rb+0
+                arb   #1
+                jt    #1, #loop
+            ",
+        )
+        .unwrap();
+
+        intcode.provide_input(7);
+        intcode.provide_input(8);
+        intcode.provide_input(9);
+        assert_eq!(intcode.resume(), Ok(StepResult::Output(7)));
+
+        let snapshot = intcode.snapshot();
+        let text = snapshot.to_string();
+        assert_eq!(
+            text,
+            "intcode-snapshot 1
+pc 6
+relative_base 1000
+inputs 8,9
+program 109,1000,203,0,204,0,109,1,1105,1,2
+extra 1000=7
+"
+        );
+
+        let parsed = text.parse::<Snapshot>().unwrap();
+        assert_eq!(parsed, snapshot);
+
+        let restored = IntCode::restore(&parsed);
+        assert_eq!(restored.snapshot(), snapshot);
+
+        for intcode in &mut [intcode, restored] {
+            assert_eq!(intcode.resume(), Ok(StepResult::Output(8)));
+            assert_eq!(intcode.resume(), Ok(StepResult::Output(9)));
+            assert_eq!(intcode.resume(), Ok(StepResult::NeedsInput));
+        }
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!("pc 0".parse::<Snapshot>(), Err(SnapshotError::BadHeader));
+        assert_eq!(
+            "intcode-snapshot 1\npc x".parse::<Snapshot>(),
+            Err(SnapshotError::BadValue { line: 2 })
+        );
+        assert_eq!(
+            "intcode-snapshot 1\npc 0".parse::<Snapshot>(),
+            Err(SnapshotError::MissingField("relative_base"))
+        );
+    }
+}
@@ -0,0 +1,600 @@
+//! Running programs over polynomials, with some cells left unknown.
+
+use super::*;
+use std::collections::{BTreeMap, HashMap};
+use std::ops::RangeInclusive;
+
+// Enough for any straight-line program, and for concrete loops around one.
+const MAX_STEPS: u64 = 1_000_000;
+
+/// A polynomial over named symbols with integer coefficients.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Polynomial {
+    // Monomials are sorted lists of symbol names, with the constant term under the
+    // empty list. Zero coefficients are never stored.
+    terms: BTreeMap<Vec<String>, IntCodeCell>,
+}
+
+impl Polynomial {
+    /// A polynomial with no symbols.
+    pub fn constant(value: IntCodeCell) -> Self {
+        let mut polynomial = Self::default();
+        polynomial.add_term(Vec::new(), value);
+        polynomial
+    }
+
+    /// The polynomial `name`.
+    pub fn symbol(name: &str) -> Self {
+        let mut polynomial = Self::default();
+        polynomial.add_term(vec![name.to_string()], 1);
+        polynomial
+    }
+
+    /// The value, if no symbols are left in it.
+    pub fn as_constant(&self) -> Option<IntCodeCell> {
+        match self.terms.iter().next() {
+            None => Some(0),
+            Some((monomial, &value)) if monomial.is_empty() && self.terms.len() == 1 => Some(value),
+            _ => None,
+        }
+    }
+
+    /// `None` if a coefficient overflows.
+    pub fn checked_add(&self, other: &Self) -> Option<Self> {
+        let mut sum = self.clone();
+        for (monomial, &value) in &other.terms {
+            sum.checked_add_term(monomial.clone(), value)?;
+        }
+        Some(sum)
+    }
+
+    /// `None` if a coefficient overflows.
+    pub fn checked_mul(&self, other: &Self) -> Option<Self> {
+        let mut product = Self::default();
+        for (left, &a) in &self.terms {
+            for (right, &b) in &other.terms {
+                let mut monomial = left.iter().chain(right).cloned().collect::<Vec<_>>();
+                monomial.sort();
+                product.checked_add_term(monomial, a.checked_mul(b)?)?;
+            }
+        }
+        Some(product)
+    }
+
+    /// `None` if a symbol has no value or the arithmetic overflows.
+    pub fn eval(&self, values: &HashMap<&str, IntCodeCell>) -> Option<IntCodeCell> {
+        self.terms
+            .iter()
+            .try_fold(0 as IntCodeCell, |sum, (monomial, &value)| {
+                let term = monomial.iter().try_fold(value, |product, name| {
+                    product.checked_mul(*values.get(name.as_str())?)
+                })?;
+                sum.checked_add(term)
+            })
+    }
+
+    /// Finds values for the symbols, each within its range, that make the polynomial
+    /// equal `target`.
+    ///
+    /// A symbol that only appears on its own, in a degree one term, is solved for
+    /// directly rather than searched, so `a*noun + verb + c` needs just one pass over
+    /// the nouns. The other symbols are searched in order, earliest values first.
+    pub fn solve(
+        &self,
+        target: IntCodeCell,
+        ranges: &[(&str, RangeInclusive<IntCodeCell>)],
+    ) -> Option<Vec<IntCodeCell>> {
+        let known = |name: &String| ranges.iter().any(|(symbol, _)| symbol == name);
+        if !self.terms.keys().flatten().all(known) {
+            return None;
+        }
+
+        let pivot = ranges.iter().rposition(|(symbol, _)| {
+            let mut containing = self
+                .terms
+                .keys()
+                .filter(|monomial| monomial.iter().any(|name| name == symbol));
+            match (containing.next(), containing.next()) {
+                (Some(monomial), None) => monomial.len() == 1,
+                _ => false,
+            }
+        });
+        let searched = (0..ranges.len())
+            .filter(|&index| Some(index) != pivot)
+            .collect::<Vec<_>>();
+
+        let mut values = vec![0; ranges.len()];
+        if self.search(target, ranges, &searched, pivot, &mut values) {
+            Some(values)
+        } else {
+            None
+        }
+    }
+
+    fn search(
+        &self,
+        target: IntCodeCell,
+        ranges: &[(&str, RangeInclusive<IntCodeCell>)],
+        searched: &[usize],
+        pivot: Option<usize>,
+        values: &mut [IntCodeCell],
+    ) -> bool {
+        if let Some((&index, rest)) = searched.split_first() {
+            return ranges[index].1.clone().any(|value| {
+                values[index] = value;
+                self.search(target, ranges, rest, pivot, values)
+            });
+        }
+
+        if let Some(pivot) = pivot {
+            // With the pivot at zero, what's left over has to come from its term.
+            let (symbol, range) = &ranges[pivot];
+            let coefficient = self.terms[&vec![symbol.to_string()]];
+            values[pivot] = 0;
+            let remainder = self
+                .eval(&named(ranges, values))
+                .and_then(|without| target.checked_sub(without));
+            match remainder {
+                Some(remainder) if remainder.checked_rem(coefficient) == Some(0) => {
+                    values[pivot] = remainder / coefficient;
+                }
+                _ => return false,
+            }
+            if !range.contains(&values[pivot]) {
+                return false;
+            }
+        }
+        self.eval(&named(ranges, values)) == Some(target)
+    }
+
+    fn add_term(&mut self, monomial: Vec<String>, value: IntCodeCell) {
+        self.checked_add_term(monomial, value).unwrap();
+    }
+
+    fn checked_add_term(&mut self, monomial: Vec<String>, value: IntCodeCell) -> Option<()> {
+        let sum = self
+            .terms
+            .get(&monomial)
+            .copied()
+            .unwrap_or(0)
+            .checked_add(value)?;
+        if sum == 0 {
+            self.terms.remove(&monomial);
+        } else {
+            self.terms.insert(monomial, sum);
+        }
+        Some(())
+    }
+}
+
+fn named<'a>(
+    ranges: &[(&'a str, RangeInclusive<IntCodeCell>)],
+    values: &[IntCodeCell],
+) -> HashMap<&'a str, IntCodeCell> {
+    ranges
+        .iter()
+        .zip(values)
+        .map(|((symbol, _), &value)| (*symbol, value))
+        .collect()
+}
+
+/// Highest degree terms first, then alphabetically, with the constant last.
+impl fmt::Display for Polynomial {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut terms = self.terms.iter().collect::<Vec<_>>();
+        terms.sort_by(|(a, _), (b, _)| b.len().cmp(&a.len()).then(a.cmp(b)));
+        if terms.is_empty() {
+            return write!(f, "0");
+        }
+
+        for (index, (monomial, &value)) in terms.into_iter().enumerate() {
+            let sign = if value < 0 { "-" } else { "+" };
+            match index {
+                0 if value < 0 => write!(f, "-")?,
+                0 => (),
+                _ => write!(f, " {} ", sign)?,
+            }
+
+            let magnitude = value.unsigned_abs();
+            if monomial.is_empty() {
+                write!(f, "{}", magnitude)?;
+            } else {
+                if magnitude != 1 {
+                    write!(f, "{}*", magnitude)?;
+                }
+                write!(f, "{}", monomial.join("*"))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Why a program couldn't be run symbolically.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[allow(missing_docs)]
+pub enum SymbolicError {
+    /// A jump or comparison on a symbolic value at `pc`.
+    DataDependent { pc: usize },
+    /// A symbolic pointer, or a symbolic instruction, at `pc`.
+    SymbolicAddress { pc: usize },
+    /// The program read at `pc` with no input queued.
+    NeedsInput { pc: usize },
+    /// The program didn't halt within a million steps.
+    TooManySteps,
+    /// The program faulted as it would running normally.
+    Fault(IntCodeError),
+}
+
+impl fmt::Display for SymbolicError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SymbolicError::DataDependent { pc } => {
+                write!(f, "pc {}: control flow depends on a symbol", pc)
+            }
+            SymbolicError::SymbolicAddress { pc } => {
+                write!(f, "pc {}: address or instruction depends on a symbol", pc)
+            }
+            SymbolicError::NeedsInput { pc } => write!(f, "pc {}: no input left", pc),
+            SymbolicError::TooManySteps => write!(f, "ran for over {} steps", MAX_STEPS),
+            SymbolicError::Fault(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for SymbolicError {}
+
+impl From<IntCodeError> for SymbolicError {
+    fn from(error: IntCodeError) -> Self {
+        SymbolicError::Fault(error)
+    }
+}
+
+// A cell read through a symbolic pointer holds the error it would cause if used.
+type Value = Result<Polynomial, SymbolicError>;
+
+/// A machine whose cells hold polynomials, so chosen cells can be left as symbols.
+///
+/// Control flow and addressing have to stay concrete: anything that depends on a
+/// symbol stops the run with an error, and the caller can fall back to searching.
+/// Reading through a symbolic pointer is only an error once the value read is used.
+///
+/// ```
+/// use aoc2019::intcode::IntCode;
+///
+/// // [0] = [9] * 3 + [10]
+/// let mut symbolic = IntCode::builder(vec![1002, 9, 3, 0, 1, 0, 10, 0, 99, 0, 0])
+///     .symbolic(&[(9, "noun"), (10, "verb")]);
+/// symbolic.run().unwrap();
+/// let result = symbolic.cell(0).unwrap();
+/// assert_eq!(result.to_string(), "3*noun + verb");
+/// assert_eq!(result.solve(19, &[("noun", 0..=9), ("verb", 0..=9)]), Some(vec![4, 7]));
+/// ```
+#[derive(Clone, Debug)]
+pub struct Symbolic {
+    memory: Vec<Value>,
+    extra: HashMap<usize, Value>,
+    pc: usize,
+    relative_base: IntCodeCell,
+    inputs: VecDeque<Polynomial>,
+    outputs: Vec<Polynomial>,
+}
+
+impl Symbolic {
+    /// A machine at pc 0 with `program` loaded, all of it concrete.
+    pub fn new(program: &[IntCodeCell]) -> Self {
+        Self {
+            memory: program
+                .iter()
+                .map(|&value| Ok(Polynomial::constant(value)))
+                .collect(),
+            extra: HashMap::new(),
+            pc: 0,
+            relative_base: 0,
+            inputs: VecDeque::new(),
+            outputs: Vec::new(),
+        }
+    }
+
+    /// Overwrites a cell, with a symbol or anything else.
+    pub fn set_cell(&mut self, address: usize, value: Polynomial) {
+        self.store(address, Ok(value));
+    }
+
+    /// Fails if the cell was last written with something read through a symbolic
+    /// pointer.
+    pub fn cell(&self, address: usize) -> Result<Polynomial, SymbolicError> {
+        match self.memory.get(address) {
+            Some(value) => value.clone(),
+            None => self
+                .extra
+                .get(&address)
+                .cloned()
+                .unwrap_or_else(|| Ok(Polynomial::default())),
+        }
+    }
+
+    /// Queues a value for the program to read.
+    pub fn provide_input(&mut self, value: Polynomial) {
+        self.inputs.push_back(value);
+    }
+
+    /// Everything output so far.
+    pub fn outputs(&self) -> &[Polynomial] {
+        &self.outputs
+    }
+
+    /// Runs until the program halts.
+    pub fn run(&mut self) -> Result<(), SymbolicError> {
+        for _ in 0..MAX_STEPS {
+            if !self.step()? {
+                return Ok(());
+            }
+        }
+        Err(SymbolicError::TooManySteps)
+    }
+
+    // Returns false once halted.
+    fn step(&mut self) -> Result<bool, SymbolicError> {
+        let raw = self.concrete(self.pc)?;
+        let instr = Instruction::new(self.pc, raw)?;
+        let data_dependent = SymbolicError::DataDependent { pc: self.pc };
+
+        match instr.opcode {
+            Add | Multiply => {
+                let result = match (self.get(instr, 1)?, self.get(instr, 2)?) {
+                    (Ok(a), Ok(b)) => Ok(instr.checked(match instr.opcode {
+                        Add => a.checked_add(&b),
+                        _ => a.checked_mul(&b),
+                    })?),
+                    (Err(error), _) | (_, Err(error)) => Err(error),
+                };
+                self.set(instr, 3, result)?;
+            }
+            LessThan | Equals => {
+                let result = match (self.get(instr, 1)?, self.get(instr, 2)?) {
+                    (Ok(a), Ok(b)) => match (a.as_constant(), b.as_constant(), instr.opcode) {
+                        (Some(a), Some(b), LessThan) => Ok(a < b),
+                        (Some(a), Some(b), _) => Ok(a == b),
+                        // Equal polynomials are equal whatever the symbols hold.
+                        _ if a == b && instr.opcode == Equals => Ok(true),
+                        _ => Err(data_dependent),
+                    },
+                    (Err(error), _) | (_, Err(error)) => Err(error),
+                };
+                self.set(
+                    instr,
+                    3,
+                    result.map(|result| Polynomial::constant(result.into())),
+                )?;
+            }
+            JumpIfTrue | JumpIfFalse => {
+                let cond = self.get(instr, 1)??.as_constant().ok_or(data_dependent)?;
+                let target = self.get(instr, 2)??.as_constant().ok_or(data_dependent)?;
+                if (cond != 0) == (instr.opcode == JumpIfTrue) {
+                    self.pc = instr.address(&target)?;
+                    return Ok(true);
+                }
+            }
+            Input => {
+                let value = self
+                    .inputs
+                    .pop_front()
+                    .ok_or(SymbolicError::NeedsInput { pc: self.pc })?;
+                self.set(instr, 1, Ok(value))?;
+            }
+            Output => {
+                let value = self.get(instr, 1)??;
+                self.outputs.push(value);
+            }
+            AdjustRelativeBase => {
+                let offset = self.get(instr, 1)??.as_constant();
+                let offset = offset.ok_or(SymbolicError::SymbolicAddress { pc: self.pc })?;
+                self.relative_base = instr.checked(self.relative_base.checked_add(offset))?;
+            }
+            Terminate => return Ok(false),
+        }
+
+        self.pc += instr.len();
+        Ok(true)
+    }
+
+    fn store(&mut self, address: usize, value: Value) {
+        match self.memory.get_mut(address) {
+            Some(cell) => *cell = value,
+            None => {
+                self.extra.insert(address, value);
+            }
+        }
+    }
+
+    fn concrete(&self, address: usize) -> Result<IntCodeCell, SymbolicError> {
+        self.cell(address)?
+            .as_constant()
+            .ok_or(SymbolicError::SymbolicAddress { pc: self.pc })
+    }
+
+    fn address(&self, instr: Instruction, offset: usize) -> Result<usize, SymbolicError> {
+        let raw = self.concrete(self.pc + offset)?;
+        Ok(match instr.modes[offset - 1] {
+            Position => instr.address(&raw)?,
+            Immediate => {
+                return Err(SymbolicError::Fault(IntCodeError::ImmediateWrite {
+                    pc: instr.pc,
+                    instruction: instr.raw,
+                }))
+            }
+            Relative => instr.address(&instr.checked(raw.checked_add(self.relative_base))?)?,
+        })
+    }
+
+    fn get(&self, instr: Instruction, offset: usize) -> Result<Value, SymbolicError> {
+        let address = match instr.modes[offset - 1] {
+            Immediate => Ok(self.pc + offset),
+            _ => self.address(instr, offset),
+        };
+        match address {
+            Ok(address) => Ok(self.cell(address)),
+            Err(error @ SymbolicError::SymbolicAddress { .. }) => Ok(Err(error)),
+            Err(error) => Err(error),
+        }
+    }
+
+    fn set(
+        &mut self,
+        instr: Instruction,
+        offset: usize,
+        value: Value,
+    ) -> Result<(), SymbolicError> {
+        let address = self.address(instr, offset)?;
+        self.store(address, value);
+        Ok(())
+    }
+}
+
+impl IntCodeBuilder {
+    /// A symbolic machine with the builder's patches and queued input, and the given
+    /// cells replaced by named symbols.
+    pub fn symbolic(&self, symbols: &[(usize, &str)]) -> Symbolic {
+        let mut symbolic = Symbolic::new(self.program());
+        for &(address, value) in self.patches() {
+            symbolic.set_cell(address, Polynomial::constant(value));
+        }
+        for &(address, name) in symbols {
+            symbolic.set_cell(address, Polynomial::symbol(name));
+        }
+        for &value in self.queued_inputs() {
+            symbolic.provide_input(Polynomial::constant(value));
+        }
+        symbolic
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn polynomials() {
+        let (x, y) = (Polynomial::symbol("x"), Polynomial::symbol("y"));
+        let three = Polynomial::constant(3);
+
+        let sum = x.checked_add(&three).unwrap();
+        let product = sum.checked_mul(&y).unwrap().checked_mul(&x).unwrap();
+        assert_eq!(product.to_string(), "x*x*y + 3*x*y");
+        let difference = x.checked_mul(&Polynomial::constant(-2)).unwrap();
+        assert_eq!(
+            difference.checked_add(&three).unwrap().to_string(),
+            "-2*x + 3"
+        );
+        assert_eq!(sum.checked_add(&difference).unwrap().to_string(), "-x + 3");
+        assert_eq!(Polynomial::default().to_string(), "0");
+
+        assert_eq!(three.as_constant(), Some(3));
+        assert_eq!(Polynomial::default().as_constant(), Some(0));
+        assert_eq!(x.as_constant(), None);
+        assert_eq!(
+            product.eval(&vec![("x", 2), ("y", 5)].into_iter().collect()),
+            Some(50)
+        );
+        assert_eq!(product.eval(&vec![("x", 2)].into_iter().collect()), None);
+
+        let huge = Polynomial::constant(IntCodeCell::MAX);
+        assert_eq!(huge.checked_mul(&x).unwrap().checked_add(&x), None);
+    }
+
+    #[test]
+    fn solve() {
+        let (x, y) = (Polynomial::symbol("x"), Polynomial::symbol("y"));
+        let ranges = [("x", 0..=99), ("y", 0..=99)];
+
+        // y is solved for directly.
+        let linear = x
+            .checked_mul(&Polynomial::constant(1000))
+            .unwrap()
+            .checked_add(&y)
+            .unwrap();
+        assert_eq!(linear.solve(42_017, &ranges), Some(vec![42, 17]));
+        assert_eq!(linear.solve(42_117, &ranges), None);
+
+        // No term stands alone, so both are searched.
+        let product = x.checked_mul(&y).unwrap();
+        assert_eq!(product.solve(91, &ranges), Some(vec![1, 91]));
+        assert_eq!(product.solve(97 * 101, &ranges), None);
+
+        assert_eq!(linear.solve(17, &ranges[1..]), None);
+        assert_eq!(y.solve(5, &ranges), Some(vec![0, 5]));
+    }
+
+    #[test]
+    fn puzzle_program() {
+        let program = include_str!("../../input/2019/day2.txt")
+            .trim()
+            .parse::<IntCodeBuilder>()
+            .unwrap();
+        let mut symbolic = program.symbolic(&[(1, "noun"), (2, "verb")]);
+        symbolic.run().unwrap();
+        let result = symbolic.cell(0).unwrap();
+
+        let values = result
+            .solve(19_690_720, &[("noun", 0..=99), ("verb", 0..=99)])
+            .unwrap();
+        let concrete = program
+            .patch(1, values[0])
+            .patch(2, values[1])
+            .build()
+            .run_no_io()
+            .unwrap();
+        assert_eq!(concrete[0], 19_690_720);
+    }
+
+    #[test]
+    fn unsupported() {
+        let run = |program: &str, symbols: &[(usize, &str)]| {
+            let mut symbolic = program.parse::<IntCodeBuilder>().unwrap().symbolic(symbols);
+            symbolic.run().map(|()| symbolic.outputs().to_vec())
+        };
+        let x = Polynomial::symbol("x");
+
+        assert_eq!(
+            run("1005,6,5,104,1,99,0", &[(6, "x")]),
+            Err(SymbolicError::DataDependent { pc: 0 })
+        );
+        assert_eq!(
+            run("1007,9,5,10,1005,10,8,99,4,3,0", &[(9, "x")]),
+            Err(SymbolicError::DataDependent { pc: 0 })
+        );
+        assert_eq!(
+            run("7,0,0,0,4,0,99", &[(0, "x")]),
+            Err(SymbolicError::SymbolicAddress { pc: 0 })
+        );
+        assert_eq!(
+            run("1,0,0,6,4,6,99", &[(1, "x")]),
+            Err(SymbolicError::SymbolicAddress { pc: 0 })
+        );
+        assert_eq!(
+            run("3,7,1007,7,5,7,99,0", &[]),
+            Err(SymbolicError::NeedsInput { pc: 0 })
+        );
+        assert_eq!(run("1105,1,0", &[]), Err(SymbolicError::TooManySteps));
+
+        // Fine as long as the symbolic parts never steer anything.
+        assert_eq!(
+            run("1107,3,5,13,1005,13,8,99,4,12,99,0,0,0", &[(12, "x")]),
+            Ok(vec![x.clone()])
+        );
+        assert_eq!(
+            run("8,7,7,7,4,7,99,0", &[(7, "x")]),
+            Ok(vec![Polynomial::constant(1)])
+        );
+        let mut symbolic = "1,0,0,7,4,8,99,0,0"
+            .parse::<IntCodeBuilder>()
+            .unwrap()
+            .symbolic(&[(1, "x"), (8, "x")]);
+        assert_eq!(symbolic.run(), Ok(()));
+        assert_eq!(symbolic.outputs(), &[x][..]);
+        assert_eq!(
+            symbolic.cell(7),
+            Err(SymbolicError::SymbolicAddress { pc: 0 })
+        );
+    }
+}
@@ -0,0 +1,362 @@
+//! Watching each instruction as it executes.
+
+use super::*;
+use std::collections::HashMap;
+use std::io;
+
+/// Something told about every instruction a traced machine runs.
+pub trait Tracer<C = IntCodeCell> {
+    /// Whether to build events at all. Only the no-op `()` tracer turns it off, which
+    /// lets untraced runs skip the work and use the compiled engine.
+    const ENABLED: bool = true;
+
+    /// Called after each instruction that ran without faulting.
+    fn trace(&mut self, event: &TraceEvent<C>);
+}
+
+impl<C> Tracer<C> for () {
+    const ENABLED: bool = false;
+
+    fn trace(&mut self, _: &TraceEvent<C>) {}
+}
+
+/// A cell written by an instruction.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MemoryWrite<C = IntCodeCell> {
+    /// Where the cell is.
+    pub address: usize,
+    /// What it held before.
+    pub old: C,
+    /// What it holds now.
+    pub new: C,
+}
+
+/// One instruction that ran.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TraceEvent<C = IntCodeCell> {
+    /// Where it is.
+    pub pc: usize,
+    /// As stored, mode digits included.
+    pub instruction: IntCodeCell,
+    /// What it does.
+    pub opcode: Opcode,
+    /// The mode of each parameter, position mode past the last one.
+    pub modes: [Mode; 3],
+    /// The values of the parameters it read. Parameters it wrote to are `None`.
+    pub operands: [Option<C>; 3],
+    /// The cell it wrote, if any.
+    pub write: Option<MemoryWrite<C>>,
+}
+
+impl<C> TraceEvent<C> {
+    pub(super) fn new(instr: Instruction) -> Self {
+        Self {
+            pc: instr.pc,
+            instruction: instr.raw,
+            opcode: instr.opcode,
+            modes: instr.modes,
+            operands: [None, None, None],
+            write: None,
+        }
+    }
+}
+
+impl<C: fmt::Display> fmt::Display for TraceEvent<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} ",
+            self.pc,
+            self.instruction,
+            self.opcode.mnemonic()
+        )?;
+
+        let mut operands = self.operands.iter().flatten().peekable();
+        if operands.peek().is_none() {
+            write!(f, "-")?;
+        }
+        while let Some(operand) = operands.next() {
+            write!(f, "{}", operand)?;
+            if operands.peek().is_some() {
+                write!(f, ",")?;
+            }
+        }
+
+        match &self.write {
+            Some(MemoryWrite { address, old, new }) => write!(f, " {}:{}>{}", address, old, new),
+            None => write!(f, " -"),
+        }
+    }
+}
+
+/// Writes each event as a line of text, in the format `Display` gives it.
+pub struct TraceWriter<W: io::Write> {
+    writer: W,
+    error: Option<io::Error>,
+}
+
+impl<W: io::Write> TraceWriter<W> {
+    /// Writes events to `writer`, which is best buffered.
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            error: None,
+        }
+    }
+
+    /// Flushes the writer and gives it back, or the first error writing to it.
+    pub fn finish(mut self) -> io::Result<W> {
+        match self.error {
+            Some(error) => Err(error),
+            None => self.writer.flush().map(|_| self.writer),
+        }
+    }
+}
+
+impl<W: io::Write, C: fmt::Display> Tracer<C> for TraceWriter<W> {
+    fn trace(&mut self, event: &TraceEvent<C>) {
+        if self.error.is_none() {
+            if let Err(error) = writeln!(self.writer, "{}", event) {
+                self.error = Some(error);
+            }
+        }
+    }
+}
+
+/// How often a straight run of instructions was executed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BlockProfile {
+    /// The address of the first instruction.
+    pub start: usize,
+    /// The address of the last cell of the last instruction.
+    pub end: usize,
+    /// How many instructions it has.
+    pub instructions: u64,
+    /// How many times it ran from start to end.
+    pub executions: u64,
+}
+
+/// Counts how often each instruction, opcode and block runs.
+#[derive(Default)]
+pub struct Profiler {
+    pc_hits: HashMap<usize, u64>,
+    opcode_hits: HashMap<Opcode, u64>,
+    // Runs from the same start can end in different places, after a jump into the
+    // middle of them or when the code changes, so each start and end is its own block.
+    blocks: HashMap<(usize, usize), BlockProfile>,
+    current_block: Option<(usize, u64)>,
+    next_pc: usize,
+}
+
+impl Profiler {
+    /// A profiler with nothing counted.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How many times the instruction at each address ran.
+    pub fn pc_hits(&self) -> &HashMap<usize, u64> {
+        &self.pc_hits
+    }
+
+    /// How many times each opcode ran.
+    pub fn opcode_hits(&self) -> &HashMap<Opcode, u64> {
+        &self.opcode_hits
+    }
+
+    /// How many instructions ran in all.
+    pub fn instructions(&self) -> u64 {
+        self.opcode_hits.values().sum()
+    }
+
+    /// The `count` addresses run most often, busiest first.
+    pub fn hottest_pcs(&self, count: usize) -> Vec<(usize, u64)> {
+        let mut pcs = self
+            .pc_hits
+            .iter()
+            .map(|(&pc, &hits)| (pc, hits))
+            .collect::<Vec<_>>();
+        pcs.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        pcs.truncate(count);
+        pcs
+    }
+
+    /// The `count` blocks that ran the most instructions in all, busiest first.
+    pub fn hottest_blocks(&self, count: usize) -> Vec<BlockProfile> {
+        let mut blocks = self.blocks.values().copied().collect::<Vec<_>>();
+        blocks.sort_by(|a, b| {
+            (b.instructions * b.executions)
+                .cmp(&(a.instructions * a.executions))
+                .then(a.start.cmp(&b.start))
+        });
+        blocks.truncate(count);
+        blocks
+    }
+
+    fn end_block(&mut self, end: usize) {
+        if let Some((start, instructions)) = self.current_block.take() {
+            let block = self.blocks.entry((start, end)).or_insert(BlockProfile {
+                start,
+                end,
+                instructions,
+                executions: 0,
+            });
+            block.executions += 1;
+        }
+    }
+}
+
+impl<C> Tracer<C> for Profiler {
+    fn trace(&mut self, event: &TraceEvent<C>) {
+        *self.pc_hits.entry(event.pc).or_insert(0) += 1;
+        *self.opcode_hits.entry(event.opcode).or_insert(0) += 1;
+
+        if event.pc != self.next_pc {
+            let end = self.next_pc.saturating_sub(1);
+            self.end_block(end);
+        }
+
+        let (_, instructions) = self.current_block.get_or_insert((event.pc, 0));
+        *instructions += 1;
+        self.next_pc = event.pc + event.opcode.parameters() + 1;
+
+        if let JumpIfTrue | JumpIfFalse | Terminate = event.opcode {
+            self.end_block(self.next_pc - 1);
+        }
+    }
+}
+
+impl fmt::Display for Profiler {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} instructions", self.instructions())?;
+
+        let mut opcodes = self.opcode_hits.iter().collect::<Vec<_>>();
+        opcodes.sort_by(|a, b| b.1.cmp(a.1));
+        for (opcode, hits) in opcodes {
+            writeln!(f, "{:>5} {}", opcode.mnemonic(), hits)?;
+        }
+
+        writeln!(f, "hottest pcs:")?;
+        for (pc, hits) in self.hottest_pcs(10) {
+            writeln!(f, "{:>8} {}", pc, hits)?;
+        }
+
+        writeln!(f, "hottest blocks:")?;
+        for block in self.hottest_blocks(10) {
+            writeln!(
+                f,
+                "{:>8}..={:<8} {} x {} instructions",
+                block.start, block.end, block.executions, block.instructions
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn countdown() -> IntCode {
+        IntCode::assemble(
+            "
+            loop:
+                add   [counter], #-1, [counter]
+                jt    [counter], #loop
+                out   [counter]
+                hlt
+            counter: .data 3
+            ",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn trace_lines() {
+        let mut writer = TraceWriter::new(Vec::new());
+        let mut intcode = countdown();
+
+        assert_eq!(
+            intcode.resume_traced(&mut writer),
+            Ok(StepResult::Output(0))
+        );
+        assert_eq!(intcode.resume_traced(&mut writer), Ok(StepResult::Halted));
+
+        assert_eq!(
+            String::from_utf8(writer.finish().unwrap()).unwrap(),
+            "0 1001 add 3,-1 10:3>2
+4 1005 jt 2,0 -
+0 1001 add 2,-1 10:2>1
+4 1005 jt 1,0 -
+0 1001 add 1,-1 10:1>0
+4 1005 jt 0,0 -
+7 4 out 0 -
+9 99 hlt - -
+"
+        );
+    }
+
+    #[test]
+    fn disabled() {
+        struct Disabled(usize);
+        impl Tracer for Disabled {
+            const ENABLED: bool = false;
+
+            fn trace(&mut self, _: &TraceEvent) {
+                self.0 += 1;
+            }
+        }
+
+        let mut tracer = Disabled(0);
+        let mut intcode = countdown();
+        assert_eq!(
+            intcode.resume_traced(&mut tracer),
+            Ok(StepResult::Output(0))
+        );
+        assert_eq!(intcode.cell(10), 0);
+        assert_eq!(tracer.0, 0);
+    }
+
+    #[test]
+    fn profile() {
+        let mut profiler = Profiler::new();
+        let mut intcode = countdown();
+        while intcode.resume_traced(&mut profiler) != Ok(StepResult::Halted) {}
+
+        assert_eq!(profiler.instructions(), 8);
+        assert_eq!(profiler.opcode_hits()[&Add], 3);
+        assert_eq!(profiler.hottest_pcs(1), vec![(0, 3)]);
+        assert_eq!(
+            profiler.hottest_blocks(2),
+            vec![
+                BlockProfile {
+                    start: 0,
+                    end: 6,
+                    instructions: 2,
+                    executions: 3,
+                },
+                BlockProfile {
+                    start: 7,
+                    end: 9,
+                    instructions: 2,
+                    executions: 1,
+                },
+            ]
+        );
+
+        // A different program from the same start is a different block.
+        let mut intcode = "99".parse::<IntCode>().unwrap();
+        assert_eq!(intcode.resume_traced(&mut profiler), Ok(StepResult::Halted));
+        assert_eq!(
+            profiler.hottest_blocks(3)[2],
+            BlockProfile {
+                start: 0,
+                end: 0,
+                instructions: 1,
+                executions: 1,
+            }
+        );
+        assert_eq!(profiler.hottest_blocks(1)[0].executions, 3);
+    }
+}
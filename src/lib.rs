@@ -3,5 +3,5 @@
 
 mod coord_system;
 pub mod days;
-mod intcode;
+pub mod intcode;
 pub mod solver;